@@ -54,7 +54,7 @@ impl Bitboards {
 
             for piece in [PieceType::ROOK, PieceType::BISHOP] {
                 for to in RangeOf::SQUARES {
-                    if Bitboards::sliding_attack(piece, from, EMPTY) & square_bb(to) == EMPTY  {
+                    if Bitboards::sliding_attack(piece, from, EMPTY) & square_bb(to) == EMPTY {
                         continue;
                     }
 
@@ -137,12 +137,12 @@ impl Bitboards {
         let to = square as isize + step;
 
         return match to {
-            to if to < 0 || to > 63 || distance(square, to as usize) > 2 => EMPTY,
+            to if !(0..=63).contains(&to) || distance(square, to as usize) > 2 => EMPTY,
             _ => 1u64 << to,
         };
     }
 
-    pub fn init_magics(piece_type: Piece, magics: &mut [Magic; NrOf::SQUARES], table: &mut Vec<Bitboard>) {
+    pub fn init_magics(piece_type: Piece, magics: &mut [Magic; NrOf::SQUARES], table: &mut [Bitboard]) {
         assert!(
             piece_type == PieceType::ROOK || piece_type == PieceType::BISHOP,
             "Invalid piece."
@@ -158,13 +158,13 @@ impl Bitboards {
             let mut occupancy: [Bitboard; 4096] = [EMPTY; 4096];
             let mut reference: [Bitboard; 4096] = [EMPTY; 4096];
 
-            let magic = &mut magics[square as usize];
+            let magic = &mut magics[square];
             magic.mask = mask;
             magic.shift = 64 - mask.count_ones() as u8;
             magic.offset = offset;
             magic.number = match piece_type {
-                PieceType::ROOK => ROOK_MAGIC_NUMBERS[square as usize],
-                PieceType::BISHOP => BISHOP_MAGIC_NUMBERS[square as usize],
+                PieceType::ROOK => ROOK_MAGIC_NUMBERS[square],
+                PieceType::BISHOP => BISHOP_MAGIC_NUMBERS[square],
                 _ => panic!("Invalid piece."),
             };
 
@@ -1,4 +1,4 @@
-use crate::defs::{Bitboard, NrOf, Square};
+use crate::defs::{rank_of, Bitboard, NrOf, Side, Sides, Square};
 
 #[derive(Debug, Default, Copy, Clone)]
 pub struct Magic {
@@ -16,12 +16,12 @@ impl Magic {
 }
 
 pub const EMPTY: Bitboard = 0;
-pub const FULL: Bitboard = 0xffffffffffffffff;
 
 pub const FILE_ABB: Bitboard = 0x0101010101010101;
 pub const FILE_HBB: Bitboard = FILE_ABB << 7;
 
 pub const RANK_1BB: Bitboard = 0xff;
+#[allow(clippy::identity_op)]
 pub const RANK_2BB: Bitboard = RANK_1BB << (8 * 1);
 pub const RANK_3BB: Bitboard = RANK_1BB << (8 * 2);
 pub const RANK_6BB: Bitboard = RANK_1BB << (8 * 5);
@@ -33,7 +33,38 @@ pub fn file_bb(square: Square) -> Bitboard {
 }
 
 pub fn rank_bb(square: Square) -> Bitboard {
-    return RANK_1BB << (square / 8) * 8;
+    return RANK_1BB << ((square / 8) * 8);
+}
+
+pub fn adjacent_files_bb(square: Square) -> Bitboard {
+    let file: Bitboard = file_bb(square);
+
+    return ((file & !FILE_HBB) << 1) | ((file & !FILE_ABB) >> 1);
+}
+
+// Ranks strictly in front of the square from the point of view of the side
+pub fn forward_ranks_bb(side: Side, square: Square) -> Bitboard {
+    return match side {
+        Sides::WHITE => !RANK_1BB << (8 * rank_of(square)),
+        _ => !RANK_8BB >> (8 * (7 - rank_of(square))),
+    };
+}
+
+pub fn forward_file_bb(side: Side, square: Square) -> Bitboard {
+    return forward_ranks_bb(side, square) & file_bb(square);
+}
+
+// Squares which must be free of enemy pawns for a pawn to be passed
+pub fn passed_pawn_span(side: Side, square: Square) -> Bitboard {
+    return forward_ranks_bb(side, square) & (adjacent_files_bb(square) | file_bb(square));
+}
+
+// Squares attacked by all the pawns of the bitboard
+pub fn pawn_attacks_bb(side: Side, pawns: Bitboard) -> Bitboard {
+    return match side {
+        Sides::WHITE => ((pawns & !FILE_ABB) << 7) | ((pawns & !FILE_HBB) << 9),
+        _ => ((pawns & !FILE_ABB) >> 9) | ((pawns & !FILE_HBB) >> 7),
+    };
 }
 
 #[rustfmt::skip]
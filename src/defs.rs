@@ -46,6 +46,8 @@ impl NrOf {
     pub const PIECE_TYPES: usize = 7;
     pub const SIDES: usize = 3;
     pub const SQUARES: usize = 64;
+    pub const FILES: usize = 8;
+    pub const CASTLING_PERMISSIONS: usize = 16;
 }
 
 pub struct RangeOf;
@@ -77,6 +79,14 @@ pub fn rank_of(square: Square) -> usize {
     return square / 8;
 }
 
+// Rank from the point of view of the side, its pawns start on relative rank 1
+pub fn relative_rank(side: Side, square: Square) -> usize {
+    return match side {
+        Sides::WHITE => rank_of(square),
+        _ => 7 - rank_of(square),
+    };
+}
+
 pub fn square_of(file: usize, rank: usize) -> Square {
     return file + rank * 8;
 }
@@ -1,57 +1,371 @@
 pub mod defs;
-mod tables;
+pub mod nnue;
+pub mod pawns;
+pub mod tables;
+mod test;
 pub mod transposition;
+pub mod weights;
 
-use crate::{defs::*, movegen::defs::Move, position::Position, search::defs::VALUE_INFINITE};
+use std::sync::Arc;
 
-use self::{defs::*, tables::*, transposition::TranspositionTable};
+use crate::{
+    bitboards::{defs::*, Bitboards},
+    defs::*,
+    misc::bits,
+    movegen::defs::pawn_push,
+    position::Position,
+    search::defs::VALUE_EVAL_MAX,
+};
 
+use self::{
+    defs::*,
+    pawns::{PawnEntry, PawnTable},
+    tables::*,
+    transposition::TranspositionTable,
+    weights::Weights,
+};
+
+// Pieces other than pawns and kings
+pub const PIECES: [Piece; 4] = [PieceType::KNIGHT, PieceType::BISHOP, PieceType::ROOK, PieceType::QUEEN];
+
+// The transposition table is shared with the evaluators of the helper search threads,
+// while each of them has its own pawn table
+#[derive(Clone)]
 pub struct Eval {
-    pub transposition_table: TranspositionTable,
+    pub transposition_table: Arc<TranspositionTable>,
+    pub pawn_table: PawnTable,
+    bitboards: Arc<Bitboards>,
 }
 
 impl Eval {
-    pub fn new() -> Self {
+    pub fn new(bitboards: Arc<Bitboards>) -> Self {
         Self {
-            transposition_table: TranspositionTable::new(DEFAULT_HASH_SIZE),
+            transposition_table: Arc::new(TranspositionTable::new(DEFAULT_HASH_SIZE)),
+            pawn_table: PawnTable::new(DEFAULT_PAWN_HASH_SIZE),
+            bitboards: bitboards,
         }
     }
 
-    pub fn evaluate(&self, position: &Position) -> i16 {
+    // Every term is computed for both sides, from their own point of view, and tapered
+    // between the middle game and the end game with the material left on the board.
+    pub fn evaluate(&mut self, position: &Position) -> i16 {
+        let state = position.states.last().unwrap();
+
+        return self.evaluate_with(position, state.psq, state.phase[Sides::WHITE] + state.phase[Sides::BLACK]);
+    }
+
+    // Computes the material and phase instead of reading the ones kept by the position,
+    // bench compares both
+    pub fn evaluate_from_scratch(&mut self, position: &Position) -> i16 {
+        let mut material: [Score; NrOf::SIDES] = [Score::ZERO; NrOf::SIDES];
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            material[side] = self.compute_material(position, side);
+        }
+
+        return self.evaluate_with(position, material, self.compute_phase(position));
+    }
+
+    fn evaluate_with(&mut self, position: &Position, material: [Score; NrOf::SIDES], phase: i16) -> i16 {
+        // The network replaces the classical evaluation when one is loaded
+        if let Some(network) = position.network() {
+            return network
+                .evaluate(position.accumulator(), position.side_to_move)
+                .clamp(-VALUE_EVAL_MAX, VALUE_EVAL_MAX);
+        }
+
         let us: Side = position.side_to_move;
         let them: Side = us ^ 1;
-        let mut middle_game: [i16; NrOf::SIDES] = [0; NrOf::SIDES];
-        let mut eng_game: [i16; NrOf::SIDES] = [0; NrOf::SIDES];
+        let pawns: PawnEntry = self.pawn_entry(position);
+        let mut scores: [Score; NrOf::SIDES] = [Score::ZERO; NrOf::SIDES];
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            scores[side] = material[side]
+                + self.mobility(position, side)
+                + pawns.scores[side]
+                + self.passed_pawns(position, side, pawns.passed[side])
+                + self.king_safety(position, side)
+                + self.pieces(position, side);
+        }
+
+        let score: Score = scores[us] - scores[them] + position.weights().tempo;
+
+        return score.taper(phase).clamp(-VALUE_EVAL_MAX, VALUE_EVAL_MAX);
+    }
+
+    // Same terms as the classical evaluation, kept apart. The material is split between the
+    // piece values and the piece square tables, and the tempo goes to the side to move.
+    pub fn trace(&mut self, position: &Position) -> EvalTrace {
+        let pawns: PawnEntry = self.pawn_entry(position);
+        let mut terms: Vec<(&'static str, [Score; 2])> = vec![
+            ("Material", [Score::ZERO; 2]),
+            ("Piece squares", [Score::ZERO; 2]),
+            ("Mobility", [Score::ZERO; 2]),
+            ("Pawns", pawns.scores),
+            ("Passed pawns", [Score::ZERO; 2]),
+            ("King safety", [Score::ZERO; 2]),
+            ("Pieces", [Score::ZERO; 2]),
+            ("Tempo", [Score::ZERO; 2]),
+        ];
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            let mut values: Score = Score::ZERO;
+
+            for piece_type in PieceType::PAWN..=PieceType::KING {
+                values += position.weights().piece_values[piece_type]
+                    * position.by_type_bb[side][piece_type].count_ones() as i16;
+            }
+
+            terms[0].1[side] = values;
+            terms[1].1[side] = self.material(position, side) - values;
+            terms[2].1[side] = self.mobility(position, side);
+            terms[4].1[side] = self.passed_pawns(position, side, pawns.passed[side]);
+            terms[5].1[side] = self.king_safety(position, side);
+            terms[6].1[side] = self.pieces(position, side);
+        }
+
+        terms[7].1[position.side_to_move] = position.weights().tempo;
+
+        let score: Score = terms
+            .iter()
+            .fold(Score::ZERO, |score, (_, sides)| score + sides[Sides::WHITE] - sides[Sides::BLACK]);
+        let phase: i16 = self.phase(position);
+        let trace = EvalTrace {
+            terms: terms,
+            phase: phase,
+            score: score.taper(phase).clamp(-VALUE_EVAL_MAX, VALUE_EVAL_MAX),
+        };
+
+        #[cfg(debug_assertions)]
+        if position.network().is_none() {
+            let sign: i16 = match position.side_to_move {
+                Sides::WHITE => 1,
+                _ => -1,
+            };
+
+            assert_eq!(trace.score, sign * self.evaluate(position));
+        }
+
+        return trace;
+    }
+
+    pub fn resize_transposition_table(&mut self, megabytes: usize) {
+        self.transposition_table = Arc::new(TranspositionTable::new(megabytes));
+    }
+
+    pub fn resize_pawn_table(&mut self, megabytes: usize) {
+        self.pawn_table = PawnTable::new(megabytes);
+    }
+
+    // Helper threads use the transposition table of the main thread, and a pawn table of the same size
+    pub fn share_tables(&mut self, main: &Eval) {
+        self.transposition_table = Arc::clone(&main.transposition_table);
+
+        if self.pawn_table.megabytes() != main.pawn_table.megabytes() {
+            self.resize_pawn_table(main.pawn_table.megabytes());
+        }
+    }
+
+    // Grows with the pieces left on the board, up to MAX_PHASE for the middle game
+    pub fn phase(&self, position: &Position) -> i16 {
+        let state = position.states.last().unwrap();
+
+        return state.phase[Sides::WHITE] + state.phase[Sides::BLACK];
+    }
+
+    // Piece values and piece square tables, kept up to date by the position as the pieces move
+    fn material(&self, position: &Position, side: Side) -> Score {
+        return position.states.last().unwrap().psq[side];
+    }
+
+    fn compute_phase(&self, position: &Position) -> i16 {
         let mut phase: i16 = 0;
 
-        for square in RangeOf::SQUARES {
-            let piece = position.board[square];
+        for piece_type in PIECES {
+            let count = (position.by_type_bb[Sides::WHITE][piece_type] | position.by_type_bb[Sides::BLACK][piece_type])
+                .count_ones() as i16;
+
+            phase += GAME_PHASE_INCREMENT[piece_type] * count;
+        }
+
+        return phase;
+    }
+
+    // The tables are laid out as seen by white, with the eighth rank first
+    fn compute_material(&self, position: &Position, side: Side) -> Score {
+        let weights = position.weights();
+        let mut score: Score = Score::ZERO;
+
+        for piece_type in PieceType::PAWN..=PieceType::KING {
+            let mut pieces: Bitboard = position.by_type_bb[side][piece_type];
 
-            if piece != PieceType::NONE {
-                let piece_type: Piece = type_of_piece(piece);
-                let color: Side = color_of_piece(piece);
-                let piece_index: Square = match color == us {
-                    true => square,
-                    false => square ^ 56,
+            while pieces != EMPTY {
+                let square: Square = bits::pop(&mut pieces);
+                let index: Square = match side {
+                    Sides::WHITE => square ^ 56,
+                    _ => square,
                 };
 
-                middle_game[color] += PIECE_VALUES_MG[piece_type] + PIECE_SQUARE_MG_TABLES[piece_type][piece_index];
-                eng_game[color] += PIECE_VALUES_EG[piece_type] + PIECE_SQUARE_EG_TABLES[piece_type][piece_index];
-                phase += GAME_PHASE_INCREMENT[type_of_piece(piece)];
+                score += weights.piece_values[piece_type] + weights.piece_squares[piece_type][index];
             }
         }
 
-        let mg_score: i16 = middle_game[us] - middle_game[them];
-        let eg_score: i16 = eng_game[us] - eng_game[them];
-        let score: i16 = match phase >= 24 {
-            true => mg_score,
-            false => (mg_score * phase + eg_score * (24 - phase)) / 24,
+        return score;
+    }
+
+    // Squares the pieces can move to, excluding the ones attacked by enemy pawns
+    fn mobility(&self, position: &Position, side: Side) -> Score {
+        let weights: &Weights = position.weights();
+        let occupied: Bitboard = position.by_color_bb[Sides::BOTH];
+        let area: Bitboard =
+            !position.by_color_bb[side] & !pawn_attacks_bb(side ^ 1, position.by_type_bb[side ^ 1][PieceType::PAWN]);
+        let mut score: Score = Score::ZERO;
+
+        for piece_type in PIECES {
+            let mut pieces: Bitboard = position.by_type_bb[side][piece_type];
+
+            while pieces != EMPTY {
+                let square: Square = bits::pop(&mut pieces);
+                let moves = (self.bitboards.attack_bb(piece_type, square, occupied) & area).count_ones() as i16;
+
+                score += weights.mobility[piece_type] * (moves - MOBILITY_CENTER[piece_type]);
+            }
+        }
+
+        return score;
+    }
+
+    fn pawn_entry(&mut self, position: &Position) -> PawnEntry {
+        let key: u64 = position.pawn_key();
+
+        if let Some(entry) = self.pawn_table.probe(key) {
+            return entry;
+        }
+
+        let mut entry = PawnEntry {
+            key: key,
+            scores: [Score::ZERO; 2],
+            passed: [EMPTY; 2],
         };
 
-        return score.min(VALUE_INFINITE).max(-VALUE_INFINITE);
+        for side in [Sides::WHITE, Sides::BLACK] {
+            (entry.scores[side], entry.passed[side]) = self.pawn_structure(position, side);
+        }
+
+        self.pawn_table.store(entry);
+
+        return entry;
     }
 
-    pub fn resize_transposition_table(&mut self, megabytes: usize) {
-        self.transposition_table = TranspositionTable::new(megabytes);
+    // Only depends on the pawns so that it can be cached, along with the passed pawns
+    fn pawn_structure(&self, position: &Position, side: Side) -> (Score, Bitboard) {
+        let weights: &Weights = position.weights();
+        let them: Side = side ^ 1;
+        let our_pawns: Bitboard = position.by_type_bb[side][PieceType::PAWN];
+        let their_pawns: Bitboard = position.by_type_bb[them][PieceType::PAWN];
+        let their_attacks: Bitboard = pawn_attacks_bb(them, their_pawns);
+        let mut pawns: Bitboard = our_pawns;
+        let mut passed: Bitboard = EMPTY;
+        let mut score: Score = Score::ZERO;
+
+        while pawns != EMPTY {
+            let square: Square = bits::pop(&mut pawns);
+            let stop_square: Square = (square as isize + pawn_push(side)) as Square;
+
+            if forward_file_bb(side, square) & our_pawns != EMPTY {
+                score += weights.doubled_pawn;
+            }
+
+            // A backward pawn cannot be supported by the pawns of the adjacent files and
+            // cannot advance safely
+            if adjacent_files_bb(square) & our_pawns == EMPTY {
+                score += weights.isolated_pawn;
+            } else if adjacent_files_bb(square) & !forward_ranks_bb(side, square) & our_pawns == EMPTY
+                && their_attacks & square_bb(stop_square) != EMPTY
+            {
+                score += weights.backward_pawn;
+            }
+
+            if passed_pawn_span(side, square) & their_pawns == EMPTY
+                && forward_file_bb(side, square) & our_pawns == EMPTY
+            {
+                score += weights.passed_pawn[relative_rank(side, square)];
+                passed |= square_bb(square);
+            }
+        }
+
+        return (score, passed);
+    }
+
+    // Passed pawns which can advance right away
+    fn passed_pawns(&self, position: &Position, side: Side, passed: Bitboard) -> Score {
+        let mut pawns: Bitboard = passed;
+        let mut score: Score = Score::ZERO;
+
+        while pawns != EMPTY {
+            let square: Square = bits::pop(&mut pawns);
+            let stop_square: Square = (square as isize + pawn_push(side)) as Square;
+
+            if position.piece_on(stop_square) == PieceType::NONE {
+                score += position.weights().passed_pawn_free * relative_rank(side, square) as i16;
+            }
+        }
+
+        return score;
+    }
+
+    // Penalty for the squares around the king attacked by enemy pieces, growing with the
+    // number of attackers
+    fn king_safety(&self, position: &Position, side: Side) -> Score {
+        let them: Side = side ^ 1;
+        let occupied: Bitboard = position.by_color_bb[Sides::BOTH];
+        let king_square: Square = bits::lsb(position.by_type_bb[side][PieceType::KING]);
+        let king_zone: Bitboard =
+            self.bitboards.attack_bb(PieceType::KING, king_square, EMPTY) | square_bb(king_square);
+        let mut attackers: usize = 0;
+        let mut penalty: Score = Score::ZERO;
+
+        for piece_type in PIECES {
+            let mut pieces: Bitboard = position.by_type_bb[them][piece_type];
+
+            while pieces != EMPTY {
+                let square: Square = bits::pop(&mut pieces);
+                let attacked = (self.bitboards.attack_bb(piece_type, square, occupied) & king_zone).count_ones() as i16;
+
+                if attacked > 0 {
+                    attackers += 1;
+                    penalty += position.weights().king_attack[piece_type] * attacked;
+                }
+            }
+        }
+
+        let scale: i16 = KING_ATTACKERS_SCALE[attackers.min(KING_ATTACKERS_SCALE.len() - 1)];
+
+        return Score::new(penalty.mg * scale / 100, penalty.eg * scale / 100);
+    }
+
+    fn pieces(&self, position: &Position, side: Side) -> Score {
+        let weights: &Weights = position.weights();
+        let our_pawns: Bitboard = position.by_type_bb[side][PieceType::PAWN];
+        let their_pawns: Bitboard = position.by_type_bb[side ^ 1][PieceType::PAWN];
+        let mut rooks: Bitboard = position.by_type_bb[side][PieceType::ROOK];
+        let mut score: Score = Score::ZERO;
+
+        if position.by_type_bb[side][PieceType::BISHOP].count_ones() >= 2 {
+            score += weights.bishop_pair;
+        }
+
+        while rooks != EMPTY {
+            let file: Bitboard = file_bb(bits::pop(&mut rooks));
+
+            if file & our_pawns == EMPTY {
+                score += match file & their_pawns == EMPTY {
+                    true => weights.rook_open_file,
+                    false => weights.rook_semi_open_file,
+                };
+            }
+        }
+
+        return score;
     }
 }
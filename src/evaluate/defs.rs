@@ -1,2 +1,82 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
 pub const PAWN_UNIT: i16 = 100;
-pub const DEFAULT_HASH_SIZE: usize = 128;
\ No newline at end of file
+pub const DEFAULT_HASH_SIZE: usize = 128;
+pub const DEFAULT_PAWN_HASH_SIZE: usize = 2;
+
+// Game phase of the starting position, the sum of the phase increments of all the pieces
+pub const MAX_PHASE: i16 = 24;
+
+// Middle game and end game values of an evaluation term, interpolated with the game phase
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Score {
+    pub mg: i16,
+    pub eg: i16,
+}
+
+impl Score {
+    pub const ZERO: Score = Score::new(0, 0);
+
+    pub const fn new(mg: i16, eg: i16) -> Self {
+        return Self { mg: mg, eg: eg };
+    }
+
+    // Interpolated in i32 as the products overflow an i16
+    pub fn taper(&self, phase: i16) -> i16 {
+        let phase: i32 = phase.min(MAX_PHASE) as i32;
+
+        return ((self.mg as i32 * phase + self.eg as i32 * (MAX_PHASE as i32 - phase)) / MAX_PHASE as i32) as i16;
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        return Score::new(self.mg + other.mg, self.eg + other.eg);
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        return Score::new(self.mg - other.mg, self.eg - other.eg);
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        return Score::new(-self.mg, -self.eg);
+    }
+}
+
+impl Mul<i16> for Score {
+    type Output = Score;
+
+    fn mul(self, factor: i16) -> Score {
+        return Score::new(self.mg * factor, self.eg * factor);
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+// Terms of the classical evaluation for each side, as shown by the eval command
+pub struct EvalTrace {
+    pub terms: Vec<(&'static str, [Score; 2])>,
+    pub phase: i16,
+    // Tapered score from white's point of view
+    pub score: i16,
+}
@@ -0,0 +1,294 @@
+use std::fs;
+
+use crate::defs::*;
+
+use super::tables::{PIECE_SQUARE_EG_TABLES, PIECE_SQUARE_MG_TABLES, PIECE_VALUES_EG, PIECE_VALUES_MG};
+
+// Efficiently updatable neural network: a hidden layer computed for each side from the
+// pieces on the board, kept up to date as pieces move, followed by a single output.
+//
+// Network file format, all values little-endian:
+//
+//   magic            4 bytes          "OXNN"
+//   version          u32              1
+//   hidden size      u32              H, a multiple of 16
+//   feature weights  i16 x 768 x H    the H weights of feature 0, then of feature 1...
+//   feature biases   i16 x H
+//   output weights   i16 x 2 x H      side to move first, then the other side
+//   output bias      i32
+//
+// The features are the pieces seen from each side: relative color * 384 + (piece type
+// - 1) * 64 + relative square, the board being flipped vertically for black. The hidden
+// layer is clipped to [0, QA] and the output is converted to centipawns with SCALE / (QA * QB).
+pub const NNUE_MAGIC: &[u8; 4] = b"OXNN";
+pub const NNUE_VERSION: u32 = 1;
+pub const FEATURES: usize = 768;
+pub const QA: i32 = 255;
+pub const QB: i32 = 64;
+pub const SCALE: i32 = 400;
+// EvalFile value keeping the classical evaluation
+pub const CLASSICAL_EVALUATION: &str = "<classical>";
+// EvalFile value selecting the network built into the binary
+pub const EMBEDDED_NETWORK: &str = "<embedded>";
+// The hidden layer is processed in chunks of this many values
+const SIMD_WIDTH: usize = 16;
+
+// Centipawns per unit of the hidden layer of the embedded network
+const EMBEDDED_UNIT: i32 = 8;
+const EMBEDDED_HIDDEN: usize = 16;
+// Keeps the king neurons positive, as the king square tables can be negative
+const EMBEDDED_KING_BIAS: i16 = 32;
+
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+// Hidden layer of each side, before activation
+#[derive(Clone, PartialEq, Debug)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    pub fn empty() -> Self {
+        return Self {
+            values: [Vec::new(), Vec::new()],
+        };
+    }
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|error| format!("Cannot read {}: {}", path, error))?;
+
+        return Network::from_bytes(&bytes);
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader {
+            bytes: bytes,
+            offset: 0,
+        };
+
+        if reader.take(4)? != NNUE_MAGIC {
+            return Err("Not a network file".to_string());
+        }
+
+        let version = reader.u32()?;
+
+        if version != NNUE_VERSION {
+            return Err(format!("Unsupported network version {}", version));
+        }
+
+        let hidden = reader.u32()? as usize;
+
+        if hidden == 0 || !hidden.is_multiple_of(SIMD_WIDTH) {
+            return Err(format!("Hidden size {} is not a multiple of {}", hidden, SIMD_WIDTH));
+        }
+
+        let network = Self {
+            hidden: hidden,
+            feature_weights: reader.i16s(FEATURES * hidden)?,
+            feature_biases: reader.i16s(hidden)?,
+            output_weights: reader.i16s(2 * hidden)?,
+            output_bias: reader.i32()?,
+        };
+
+        if reader.offset != bytes.len() {
+            return Err("Unexpected data at the end of the network file".to_string());
+        }
+
+        return Ok(network);
+    }
+
+    #[allow(dead_code)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+
+        bytes.extend_from_slice(NNUE_MAGIC);
+        bytes.extend_from_slice(&NNUE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden as u32).to_le_bytes());
+
+        for values in [&self.feature_weights, &self.feature_biases, &self.output_weights] {
+            for value in values.iter() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+
+        return bytes;
+    }
+
+    // Tiny network built into the binary, reproducing the material and piece square
+    // tables of the classical evaluation, averaged between the middle game and the end
+    // game. Each neuron sums the pieces of one type and one side, in units of 8 cp.
+    pub fn embedded() -> Self {
+        let hidden = EMBEDDED_HIDDEN;
+        let mut feature_weights: Vec<i16> = vec![0; FEATURES * hidden];
+        let mut feature_biases: Vec<i16> = vec![0; hidden];
+        let mut output_weights: Vec<i16> = vec![0; 2 * hidden];
+        let output_weight = (EMBEDDED_UNIT * QA * QB / SCALE) as i16;
+
+        for relative_color in 0..2 {
+            for piece_type in PieceType::PAWN..=PieceType::KING {
+                let neuron = relative_color * 6 + piece_type - 1;
+
+                for square in RangeOf::SQUARES {
+                    // The pieces of the side are seen as white, the other ones as black
+                    let index = match relative_color {
+                        0 => square ^ 56,
+                        _ => square,
+                    };
+                    let value = (PIECE_VALUES_MG[piece_type] as i32
+                        + PIECE_SQUARE_MG_TABLES[piece_type][index] as i32
+                        + PIECE_VALUES_EG[piece_type] as i32
+                        + PIECE_SQUARE_EG_TABLES[piece_type][index] as i32)
+                        / 2;
+                    let feature = relative_color * 384 + (piece_type - 1) * 64 + square;
+
+                    feature_weights[feature * hidden + neuron] = (value as f64 / EMBEDDED_UNIT as f64).round() as i16;
+                }
+
+                if piece_type == PieceType::KING {
+                    feature_biases[neuron] = EMBEDDED_KING_BIAS;
+                }
+
+                output_weights[neuron] = match relative_color {
+                    0 => output_weight,
+                    _ => -output_weight,
+                };
+            }
+        }
+
+        return Self {
+            hidden: hidden,
+            feature_weights: feature_weights,
+            feature_biases: feature_biases,
+            output_weights: output_weights,
+            // The biases of both kings cancel out
+            output_bias: 0,
+        };
+    }
+
+    pub fn new_accumulator(&self) -> Accumulator {
+        return Accumulator {
+            values: [self.feature_biases.clone(), self.feature_biases.clone()],
+        };
+    }
+
+    pub fn add(&self, accumulator: &mut Accumulator, piece: Piece, square: Square) {
+        for perspective in [Sides::WHITE, Sides::BLACK] {
+            let weights = self.weights(perspective, piece, square);
+
+            for (value, &weight) in accumulator.values[perspective].iter_mut().zip(weights) {
+                *value = value.wrapping_add(weight);
+            }
+        }
+    }
+
+    pub fn remove(&self, accumulator: &mut Accumulator, piece: Piece, square: Square) {
+        for perspective in [Sides::WHITE, Sides::BLACK] {
+            let weights = self.weights(perspective, piece, square);
+
+            for (value, &weight) in accumulator.values[perspective].iter_mut().zip(weights) {
+                *value = value.wrapping_sub(weight);
+            }
+        }
+    }
+
+    // Removing and adding the piece in a single pass
+    pub fn move_piece(&self, accumulator: &mut Accumulator, piece: Piece, from: Square, to: Square) {
+        for perspective in [Sides::WHITE, Sides::BLACK] {
+            let removed = self.weights(perspective, piece, from);
+            let added = self.weights(perspective, piece, to);
+
+            for ((value, &removed), &added) in accumulator.values[perspective].iter_mut().zip(removed).zip(added) {
+                *value = value.wrapping_add(added.wrapping_sub(removed));
+            }
+        }
+    }
+
+    // Score in centipawns from the point of view of the side. The output of wide hidden
+    // layers does not fit in an i32, and the score is clamped to the range of an i16.
+    pub fn evaluate(&self, accumulator: &Accumulator, side: Side) -> i16 {
+        let (ours, theirs) = self.output_weights.split_at(self.hidden);
+        let output: i64 = Network::activate(&accumulator.values[side], ours)
+            + Network::activate(&accumulator.values[side ^ 1], theirs)
+            + self.output_bias as i64;
+        let score: i64 = output * SCALE as i64 / (QA * QB) as i64;
+
+        return score.clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+    }
+
+    // Clipped ReLU followed by the dot product with the output weights. Processing fixed
+    // size chunks of integers lets the compiler vectorize the loop, the sum of a chunk
+    // always fits in an i32.
+    fn activate(values: &[i16], weights: &[i16]) -> i64 {
+        return values
+            .chunks_exact(SIMD_WIDTH)
+            .zip(weights.chunks_exact(SIMD_WIDTH))
+            .map(|(values, weights)| {
+                values
+                    .iter()
+                    .zip(weights)
+                    .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+                    .sum::<i32>() as i64
+            })
+            .sum();
+    }
+
+    fn weights(&self, perspective: Side, piece: Piece, square: Square) -> &[i16] {
+        let feature = feature_index(perspective, piece, square);
+
+        return &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden];
+    }
+}
+
+fn feature_index(perspective: Side, piece: Piece, square: Square) -> usize {
+    let relative_color: usize = (color_of_piece(piece) != perspective) as usize;
+    let relative_square: Square = match perspective {
+        Sides::WHITE => square,
+        _ => square ^ 56,
+    };
+
+    return relative_color * 384 + (type_of_piece(piece) - 1) * 64 + relative_square;
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.offset + length > self.bytes.len() {
+            return Err("Truncated network file".to_string());
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + length];
+        self.offset += length;
+
+        return Ok(bytes);
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        return Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, String> {
+        return Ok(self
+            .take(2 * count)?
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect());
+    }
+}
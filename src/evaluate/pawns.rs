@@ -0,0 +1,80 @@
+use crate::{bitboards::defs::EMPTY, defs::*};
+
+use super::defs::Score;
+
+// Pawn structures repeat a lot in the tree, their evaluation is cached by pawn key.
+// Each search thread has its own table, so it is a plain always-replace table.
+#[derive(Clone)]
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+    size: usize,
+    pub hits: usize,
+    pub probes: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct PawnEntry {
+    pub key: u64,
+    // Pawn structure score and passed pawns of each side
+    pub scores: [Score; 2],
+    pub passed: [Bitboard; 2],
+}
+
+impl PawnEntry {
+    fn empty() -> Self {
+        return Self {
+            key: 0,
+            scores: [Score::ZERO; 2],
+            passed: [EMPTY; 2],
+        };
+    }
+}
+
+impl PawnTable {
+    // Size is in MB
+    pub fn new(megabytes: usize) -> Self {
+        let size = megabytes * 1024 * 1024 / std::mem::size_of::<PawnEntry>();
+
+        return Self {
+            entries: vec![PawnEntry::empty(); size],
+            size: size,
+            hits: 0,
+            probes: 0,
+        };
+    }
+
+    pub fn megabytes(&self) -> usize {
+        return self.size * std::mem::size_of::<PawnEntry>() / (1024 * 1024);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = vec![PawnEntry::empty(); self.size];
+        self.hits = 0;
+        self.probes = 0;
+    }
+
+    // Positions without pawns have a zero key and match the empty entries, whose scores are zero too
+    pub fn probe(&mut self, key: u64) -> Option<PawnEntry> {
+        let entry = self.entries[(key % self.size as u64) as usize];
+
+        self.probes += 1;
+
+        if entry.key == key {
+            self.hits += 1;
+            return Some(entry);
+        }
+
+        return None;
+    }
+
+    pub fn store(&mut self, entry: PawnEntry) {
+        let index = (entry.key % self.size as u64) as usize;
+
+        self.entries[index] = entry;
+    }
+
+    // Percentage of the probes finding the pawn structure
+    pub fn hit_rate(&self) -> f64 {
+        return self.hits as f64 * 100.0 / self.probes.max(1) as f64;
+    }
+}
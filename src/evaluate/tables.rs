@@ -1,10 +1,60 @@
 use crate::defs::NrOf;
 
+use super::defs::Score;
+
 pub const GAME_PHASE_INCREMENT: [i16; NrOf::PIECE_TYPES] = [0, 0, 1, 1, 2, 4, 0];
 pub const PIECE_VALUES_INITIAL: [i16; NrOf::PIECE_TYPES] = [0, 100, 300, 300, 500, 900, 0];
 pub const PIECE_VALUES_MG: [i16; NrOf::PIECE_TYPES] = [0, 82, 337, 365, 477, 1025, 0];
 pub const PIECE_VALUES_EG: [i16; NrOf::PIECE_TYPES] = [0, 94, 281, 297, 512, 936, 0];
 
+// Bonus per square a piece can move to, counted from the usual number of squares of the piece
+pub const MOBILITY_WEIGHTS: [Score; NrOf::PIECE_TYPES] = [
+    Score::ZERO,
+    Score::ZERO,
+    Score::new(4, 4),
+    Score::new(5, 5),
+    Score::new(2, 4),
+    Score::new(1, 2),
+    Score::ZERO,
+];
+pub const MOBILITY_CENTER: [i16; NrOf::PIECE_TYPES] = [0, 0, 4, 7, 7, 14, 0];
+
+pub const DOUBLED_PAWN: Score = Score::new(-10, -20);
+pub const ISOLATED_PAWN: Score = Score::new(-10, -10);
+pub const BACKWARD_PAWN: Score = Score::new(-8, -10);
+// Indexed by the relative rank of the pawn
+pub const PASSED_PAWN: [Score; 8] = [
+    Score::ZERO,
+    Score::new(5, 10),
+    Score::new(5, 15),
+    Score::new(10, 25),
+    Score::new(20, 45),
+    Score::new(40, 80),
+    Score::new(70, 130),
+    Score::ZERO,
+];
+// Bonus per relative rank of a passed pawn whose next square is empty
+pub const PASSED_PAWN_FREE: Score = Score::new(2, 6);
+
+// Penalty per square of the king zone attacked by an enemy piece
+pub const KING_ATTACK_WEIGHTS: [Score; NrOf::PIECE_TYPES] = [
+    Score::ZERO,
+    Score::ZERO,
+    Score::new(-6, -1),
+    Score::new(-6, -1),
+    Score::new(-8, -2),
+    Score::new(-12, -4),
+    Score::ZERO,
+];
+// Percentage of the king attack penalty applied, indexed by the number of attacking pieces.
+// A single attacker is rarely dangerous.
+pub const KING_ATTACKERS_SCALE: [i16; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+pub const BISHOP_PAIR: Score = Score::new(30, 50);
+pub const ROOK_OPEN_FILE: Score = Score::new(25, 10);
+pub const ROOK_SEMI_OPEN_FILE: Score = Score::new(10, 5);
+pub const TEMPO: Score = Score::new(15, 0);
+
 pub const PIECE_SQUARE_MG_TABLES: [[i16; NrOf::SQUARES]; NrOf::PIECE_TYPES] = [
     [0; NrOf::SQUARES],
     PAWN_MG_TABLE,
@@ -25,6 +75,7 @@ pub const PIECE_SQUARE_EG_TABLES: [[i16; NrOf::SQUARES]; NrOf::PIECE_TYPES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const PAWN_MG_TABLE: [i16; NrOf::SQUARES] = [
     0000, 0000, 0000, 0000, 0000, 0000, 0000, 0000,
     0098, 0134, 0061, 0095, 0068, 0126, 0034, -011,
@@ -37,6 +88,7 @@ const PAWN_MG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const PAWN_EG_TABLE: [i16; NrOf::SQUARES] = [
     0000, 0000, 0000, 0000, 0000, 0000, 0000, 0000,
     0178, 0173, 0158, 0134, 0147, 0132, 0165, 0187,
@@ -49,6 +101,7 @@ const PAWN_EG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const KNIGHT_MG_TABLE: [i16; NrOf::SQUARES] = [
     -167, -089, -034, -049, 0061, -097, -015, -107,
     -073, -041, 0072, 0036, 0023, 0062, 0007, -017,
@@ -61,6 +114,7 @@ const KNIGHT_MG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const KNIGHT_EG_TABLE: [i16; NrOf::SQUARES] = [
     -058, -038, -013, -028, -031, -027, -063, -099,
     -025, -008, -025, -002, -009, -025, -024, -052,
@@ -73,6 +127,7 @@ const KNIGHT_EG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const BISHOP_MG_TABLE: [i16; NrOf::SQUARES] = [
     -029, 0004, -082, -037, -025, -042, 0007, -008,
     -026, 0016, -018, -013, 0030, 0059, 0018, -047,
@@ -85,6 +140,7 @@ const BISHOP_MG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const BISHOP_EG_TABLE: [i16; NrOf::SQUARES] = [
     -014, -021, -011, -008, -007, -009, -017, -024,
     -008, -004, 0007, -012, -003, -013, -004, -014,
@@ -97,6 +153,7 @@ const BISHOP_EG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const ROOK_MG_TABLE: [i16; NrOf::SQUARES] = [
     0032, 0042, 0032, 0051, 0063, 0009, 0031, 0043,
     0027, 0032, 0058, 0062, 0080, 0067, 0026, 0044,
@@ -109,6 +166,7 @@ const ROOK_MG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const ROOK_EG_TABLE: [i16; NrOf::SQUARES] = [
     0013, 0010, 0018, 0015, 0012, 0012, 0008, 0005,
     0011, 0013, 0013, 0011, -003, 0003, 0008, 0003,
@@ -121,6 +179,7 @@ const ROOK_EG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const QUEEN_MG_TABLE: [i16; NrOf::SQUARES] = [
     -028, 0000, 0029, 0012, 0059, 0044, 0043, 0045,
     -024, -039, -005, 0001, -016, 0057, 0028, 0054,
@@ -133,6 +192,7 @@ const QUEEN_MG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const QUEEN_EG_TABLE: [i16; NrOf::SQUARES] = [
     -009, 0022, 0022, 0027, 0027, 0019, 0010, 0020,
     -017, 0020, 0032, 0041, 0058, 0025, 0030, 0000,
@@ -145,6 +205,7 @@ const QUEEN_EG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const KING_MG_TABLE: [i16; NrOf::SQUARES] = [
     -065, 0023, 0016, -015, -056, -034, 0002, 0013,
     0029, -001, -020, -007, -008, -004, -038, -029,
@@ -157,6 +218,7 @@ const KING_MG_TABLE: [i16; NrOf::SQUARES] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::zero_prefixed_literal)]
 const KING_EG_TABLE: [i16; NrOf::SQUARES] = [
     -074, -035, -018, -018, -011, 0015, 0004, -017,
     -012, 0017, 0014, 0017, 0017, 0038, 0023, 0011,
@@ -0,0 +1,455 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        bitboards::Bitboards,
+        defs::{PieceType, Sides},
+        evaluate::{
+            defs::{EvalTrace, Score},
+            nnue::{Network, FEATURES, NNUE_MAGIC, NNUE_VERSION, QA},
+            tables::KING_ATTACKERS_SCALE,
+            transposition::{HashData, NodeType, TranspositionTable},
+            weights::Weights,
+            Eval,
+        },
+        movegen::{
+            defs::{Move, MoveTypes},
+            Movegen,
+        },
+        position::Position,
+        search::defs::{FEN_START_POSITION, VALUE_EVAL_MAX, VALUE_MATE},
+    };
+
+    fn hash_data(depth: u8, best_move: Move) -> HashData {
+        return HashData {
+            depth: depth,
+            value: 0,
+            best_move: best_move,
+            node_type: NodeType::EXACT,
+        };
+    }
+
+    #[test]
+    fn transposition_replacement() {
+        // A 1MB table has 16384 buckets, so these keys all land in the first bucket
+        let table = TranspositionTable::new(1);
+        let keys: [u64; 5] = [16384, 2 * 16384, 3 * 16384, 4 * 16384, 5 * 16384];
+
+        for (i, &key) in keys[0..4].iter().enumerate() {
+            table.store(key, hash_data(10 - i as u8, Move::with_from_to(12, 28)));
+        }
+
+        // The bucket is full, the shallowest entry is replaced
+        table.store(keys[4], hash_data(5, Move::with_from_to(12, 28)));
+        assert!(table.probe(keys[3]).is_none());
+        assert!(table.probe(keys[4]).is_some());
+
+        // Entries of previous searches are replaced even if they are deeper
+        table.new_search();
+        table.store(keys[4], hash_data(3, Move::with_from_to(12, 28)));
+        table.store(keys[3], hash_data(1, Move::none()));
+        assert!(table.probe(keys[2]).is_none());
+        assert!(table.probe(keys[4]).is_some());
+        assert_eq!(table.probe(keys[3]).unwrap().depth, 1);
+
+        // Storing without a best move keeps the existing one
+        table.store(keys[1], hash_data(2, Move::none()));
+        assert!(table.probe(keys[1]).unwrap().best_move == Move::with_from_to(12, 28));
+
+        table.clear();
+        assert!(table.probe(keys[1]).is_none());
+        assert_eq!(table.hashfull(), 0);
+    }
+
+    #[test]
+    fn transposition_packing() {
+        let table = TranspositionTable::new(1);
+        let best_move = Move::make(52, 60, PieceType::QUEEN, MoveTypes::PROMOTION);
+
+        for (key, value, node_type) in [
+            (1, -VALUE_MATE, NodeType::UPPERBOUND),
+            (2, VALUE_MATE, NodeType::LOWERBOUND),
+            (3, -1, NodeType::EXACT),
+        ] {
+            table.store(
+                key,
+                HashData {
+                    depth: u8::MAX,
+                    value: value,
+                    best_move: best_move,
+                    node_type: node_type,
+                },
+            );
+
+            let data = table.probe(key).unwrap();
+            assert_eq!(data.depth, u8::MAX);
+            assert_eq!(data.value, value);
+            assert!(data.best_move == best_move);
+            assert!(data.node_type == node_type);
+        }
+    }
+
+    // Same position with the colors swapped and the board flipped vertically
+    fn mirror_fen(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect()
+        };
+        let board: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let side = match fields[1] {
+            "w" => "b",
+            _ => "w",
+        };
+        let en_passant: String = match fields[3] {
+            "-" => "-".to_string(),
+            square => square
+                .chars()
+                .map(|c| match c {
+                    '3' => '6',
+                    '6' => '3',
+                    _ => c,
+                })
+                .collect(),
+        };
+
+        return format!(
+            "{} {} {} {} 0 1",
+            board.join("/"),
+            side,
+            swap_case(fields[2]),
+            en_passant
+        );
+    }
+
+    #[test]
+    fn evaluation_symmetry() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let mut mirrored = Position::new(Arc::clone(&bitboards));
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "6k1/5ppp/8/3P4/8/2B5/5PPP/3R2K1 b - - 0 1",
+        ] {
+            position.set(fen.to_string());
+            mirrored.set(mirror_fen(fen));
+
+            let score = eval.evaluate(&position);
+            assert_eq!(score, eval.evaluate(&mirrored), "{}", fen);
+
+            // Computing the material and phase from scratch, as bench does, gives the same score
+            assert_eq!(eval.evaluate_from_scratch(&position), score, "{}", fen);
+        }
+
+        // White is a rook, a bishop and a pawn up
+        position.set("6k1/5ppp/8/3P4/8/2B5/5PPP/3R2K1 w - - 0 1".to_string());
+        assert!(eval.evaluate(&position) > 0);
+    }
+
+    #[test]
+    fn pawn_table() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        position.set("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10".to_string());
+
+        let score = eval.evaluate(&position);
+        assert_eq!(eval.pawn_table.hits, 0);
+
+        // Same pawns with the pieces elsewhere
+        position.set("r4rk1/1pp1qppp/p1np4/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RKn w - - 0 10".to_string());
+        eval.evaluate(&position);
+        assert_eq!(eval.pawn_table.hits, 1);
+
+        position.set("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10".to_string());
+        assert_eq!(eval.evaluate(&position), score);
+        assert_eq!(eval.pawn_table.hits, 2);
+        assert_eq!(eval.pawn_table.probes, 3);
+    }
+
+    #[test]
+    fn nnue_file_format() {
+        let bytes = Network::embedded().to_bytes();
+        let network = Network::from_bytes(&bytes).unwrap();
+
+        assert!(network.to_bytes() == bytes);
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+        assert!(Network::from_bytes(&[b"NNUE", &bytes[4..]].concat()).is_err());
+    }
+
+    #[test]
+    fn nnue_wide_output() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let hidden: usize = 1024;
+        let mut bytes: Vec<u8> = [
+            &NNUE_MAGIC[..],
+            &NNUE_VERSION.to_le_bytes(),
+            &(hidden as u32).to_le_bytes(),
+        ]
+        .concat();
+
+        // Every neuron is active and weighted as much as possible, far beyond the range of an i32
+        bytes.extend(vec![0u8; 2 * FEATURES * hidden]);
+        bytes.extend((QA as i16).to_le_bytes().repeat(hidden));
+        bytes.extend(i16::MAX.to_le_bytes().repeat(2 * hidden));
+        bytes.extend(0i32.to_le_bytes());
+
+        let network = Arc::new(Network::from_bytes(&bytes).unwrap());
+        let accumulator = network.new_accumulator();
+
+        assert_eq!(network.evaluate(&accumulator, Sides::WHITE), i16::MAX);
+
+        // The evaluation stays below the mate scores
+        position.set_network(Some(network));
+        position.set(FEN_START_POSITION.to_string());
+        assert_eq!(eval.evaluate(&position), VALUE_EVAL_MAX);
+    }
+
+    #[test]
+    fn nnue_accumulator() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let network = Arc::new(Network::embedded());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let mut refreshed = Position::new(Arc::clone(&bitboards));
+
+        position.set_network(Some(Arc::clone(&network)));
+        refreshed.set_network(Some(Arc::clone(&network)));
+
+        // Castling, en passant, promotions and captures
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+        ] {
+            position.set(fen.to_string());
+            let accumulator = position.accumulator().clone();
+
+            // The incremental updates match an accumulator computed from scratch
+            for mv in movegen.legal_moves(&position).iter() {
+                position.do_move(*mv);
+                refreshed.set(position.fen());
+                assert_eq!(position.accumulator(), refreshed.accumulator());
+                assert_eq!(eval.evaluate(&position), eval.evaluate(&refreshed));
+
+                position.undo_move(*mv);
+                assert_eq!(position.accumulator(), &accumulator);
+            }
+
+            // The network sees both sides the same way
+            refreshed.set(mirror_fen(fen));
+            assert_eq!(eval.evaluate(&position), eval.evaluate(&refreshed), "{}", fen);
+        }
+
+        // Without a network the classical evaluation is used again
+        position.set_network(None);
+        assert!(position.network().is_none());
+    }
+
+    #[test]
+    fn weights_file() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let defaults = Weights::default();
+
+        assert!(Weights::from_text(&defaults.to_text()).unwrap() == defaults);
+
+        // Missing keys keep their default values
+        let weights = Weights::from_text("# Stronger knights\npiece_values_mg = [82, 400, 365, 477, 1025, 0]").unwrap();
+        assert_eq!(weights.piece_values[PieceType::KNIGHT].mg, 400);
+        assert_eq!(weights.piece_values[PieceType::KNIGHT].eg, defaults.piece_values[PieceType::KNIGHT].eg);
+        assert!(weights.piece_squares == defaults.piece_squares);
+
+        // The other terms are loaded the same way
+        let pair = Weights::from_text("bishop_pair_eg = [80]\nmobility_mg = [1, 2, 3, 4]").unwrap();
+        assert_eq!(pair.bishop_pair, Score::new(defaults.bishop_pair.mg, 80));
+        assert_eq!(pair.mobility[PieceType::QUEEN].mg, 4);
+        assert!(Weights::from_text("tempo_mg = [10, 10]").is_err());
+
+        // Values which could overflow the sums of the evaluation are rejected
+        let error = Weights::from_text("piece_values_eg = [94, 281, 297, 512, 20000, 0]").unwrap_err();
+        assert!(error.contains("piece_values_eg"), "{}", error);
+        assert!(Weights::from_text("tempo_mg = [501]").is_err());
+        assert!(Weights::from_text("tempo_eg = [-32768]").is_err());
+
+        // The largest ones are still evaluated with every piece on the board
+        let mut largest = defaults.clone();
+
+        for piece_type in PieceType::PAWN..=PieceType::KING {
+            largest.piece_values[piece_type] = Score::new(1500, -1500);
+            largest.piece_squares[piece_type] = [Score::new(500, -500); 64];
+        }
+
+        position.set_weights(Arc::new(largest));
+        position.set(FEN_START_POSITION.to_string());
+        eval.evaluate(&position);
+        position.set_weights(Arc::new(defaults.clone()));
+
+        assert!(Weights::from_text("piece_values_mg = [82, 400]").is_err());
+        assert!(Weights::from_text("knight_mid = [0]").is_err());
+        assert!(Weights::from_text("pawn_eg = [0, x]").is_err());
+        assert!(Weights::from_text("pawn_eg = [0").is_err());
+
+        // White has an extra knight
+        position.set("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1".to_string());
+        let score = eval.evaluate(&position);
+        position.set_weights(Arc::new(weights));
+        assert!(eval.evaluate(&position) > score);
+
+        // The states of the moves played before the change are brought up to date when undone
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mv = movegen.legal_moves(&position)[0];
+        let score = eval.evaluate(&position);
+        position.do_move(mv);
+        position.set_weights(Arc::new(defaults));
+        position.undo_move(mv);
+        assert!(eval.evaluate(&position) < score);
+        assert_eq!(position.states.len(), 1);
+    }
+
+    #[test]
+    fn evaluation_trace() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "6k1/5ppp/8/3P4/8/2B5/5PPP/3R2K1 b - - 0 1",
+        ] {
+            position.set(fen.to_string());
+
+            let trace = eval.trace(&position);
+            let sign: i16 = match position.side_to_move {
+                Sides::WHITE => 1,
+                _ => -1,
+            };
+
+            // The terms add up to the evaluation, seen from white's side
+            assert_eq!(trace.score, sign * eval.evaluate(&position), "{}", fen);
+            assert_eq!(trace.phase, eval.phase(&position));
+        }
+
+        // The starting position only differs by the tempo
+        position.set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+        let trace = eval.trace(&position);
+
+        for (name, sides) in trace.terms.iter() {
+            match *name {
+                "Tempo" => assert!(sides[Sides::WHITE] != sides[Sides::BLACK]),
+                _ => assert!(sides[Sides::WHITE] == sides[Sides::BLACK], "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn evaluation_terms() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let defaults = Weights::default();
+
+        // Each pawn term is counted on its own, the others having no weight
+        let mut count = |fen: &str, weight: fn(&mut Weights)| -> [Score; 2] {
+            let mut weights = Weights::default();
+            weights.doubled_pawn = Score::ZERO;
+            weights.isolated_pawn = Score::ZERO;
+            weights.backward_pawn = Score::ZERO;
+            weights.passed_pawn = [Score::ZERO; 8];
+            weight(&mut weights);
+
+            eval.pawn_table.clear();
+            position.set_weights(Arc::new(weights));
+            position.set(fen.to_string());
+
+            return term(&eval.trace(&position), "Pawns");
+        };
+
+        // The e2 pawn is behind another one, the pawns of the a and c files have no neighbours.
+        // The c3 pawn supports the d2 one, whose next square is attacked by the e4 pawn.
+        let one = Score::new(1, 1);
+        let doubled = count("4k3/p7/8/8/8/4P3/4P3/4K3 w - - 0 1", |weights| {
+            weights.doubled_pawn = Score::new(1, 1)
+        });
+        let isolated = count("4k3/p7/8/8/8/8/P1P5/4K3 w - - 0 1", |weights| {
+            weights.isolated_pawn = Score::new(1, 1)
+        });
+        let backward = count("4k3/8/8/8/4p3/2P5/3P4/4K3 w - - 0 1", |weights| {
+            weights.backward_pawn = Score::new(1, 1)
+        });
+        let passed = count("4k3/7p/8/P7/8/8/8/4K3 w - - 0 1", |weights| {
+            weights.passed_pawn = Weights::default().passed_pawn
+        });
+
+        assert_eq!(doubled, [one, Score::ZERO]);
+        assert_eq!(isolated, [one * 2, one]);
+        assert_eq!(backward, [one, Score::ZERO]);
+        assert_eq!(passed, [defaults.passed_pawn[4], defaults.passed_pawn[1]]);
+
+        // Both passed pawns can advance
+        position.set_weights(Arc::new(defaults.clone()));
+        eval.pawn_table.clear();
+        let trace = eval.trace(&position);
+        assert_eq!(
+            term(&trace, "Passed pawns"),
+            [defaults.passed_pawn_free * 4, defaults.passed_pawn_free]
+        );
+
+        // Rooks on an open and a semi-open file for white, on a semi-open file for black
+        position.set("1r2k3/p7/8/8/8/8/1P6/R2RK3 w - - 0 1".to_string());
+        let trace = eval.trace(&position);
+        assert_eq!(
+            term(&trace, "Pieces"),
+            [
+                defaults.rook_open_file + defaults.rook_semi_open_file,
+                defaults.rook_semi_open_file
+            ]
+        );
+
+        // Only white has the bishop pair
+        position.set("2b1k3/8/8/8/8/8/8/2B1KB2 w - - 0 1".to_string());
+        let trace = eval.trace(&position);
+        assert_eq!(term(&trace, "Pieces"), [defaults.bishop_pair, Score::ZERO]);
+
+        // The knight attacks f7 and h7 around the black king, the queen h7
+        position.set("6k1/5ppp/8/6N1/8/3Q4/8/4K3 w - - 0 1".to_string());
+        let trace = eval.trace(&position);
+        let penalty = defaults.king_attack[PieceType::KNIGHT] * 2 + defaults.king_attack[PieceType::QUEEN];
+        let scale = KING_ATTACKERS_SCALE[2];
+        assert_eq!(
+            term(&trace, "King safety"),
+            [
+                Score::ZERO,
+                Score::new(penalty.mg * scale / 100, penalty.eg * scale / 100)
+            ]
+        );
+        assert!(term(&trace, "King safety")[Sides::BLACK].mg < 0);
+
+        // A single attacker is not enough
+        position.set("6k1/5ppp/8/6N1/8/8/8/4K3 w - - 0 1".to_string());
+        let trace = eval.trace(&position);
+        assert_eq!(term(&trace, "King safety"), [Score::ZERO; 2]);
+    }
+
+    // Scores of a term of the trace for white and black
+    fn term(trace: &EvalTrace, name: &str) -> [Score; 2] {
+        return trace.terms.iter().find(|(term, _)| *term == name).unwrap().1;
+    }
+}
@@ -1,15 +1,31 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
 use crate::movegen::defs::Move;
 
+const ENTRIES_PER_BUCKET: usize = 4;
+// Number of buckets sampled to estimate how full the table is
+const HASHFULL_SAMPLE: usize = 250;
+
+// The table is shared by all the search threads without locking. Each entry stores its
+// data packed in a single word, and its key XORed with that word. An entry torn by two
+// threads writing at the same time no longer matches its key and is simply ignored.
 pub struct TranspositionTable {
-    entries: Vec<Entry>,
+    buckets: Vec<Bucket>,
     size: usize,
+    generation: AtomicU8,
 }
 
-#[derive(Copy, Clone)]
 pub struct Entry {
-    key: u64,
-    data: HashData,
+    key: AtomicU64,
+    data: AtomicU64,
 }
+
+// Buckets are aligned to a cache line so that probing a position only needs a single memory access
+#[repr(align(64))]
+struct Bucket {
+    entries: [Entry; ENTRIES_PER_BUCKET],
+}
+
 #[derive(Copy, Clone)]
 pub struct HashData {
     pub depth: u8,
@@ -18,17 +34,7 @@ pub struct HashData {
     pub node_type: NodeType,
 }
 
-impl HashData {
-    pub fn default() -> Self {
-        return Self {
-            depth: 0,
-            value: 0,
-            best_move: Move::none(),
-            node_type: NodeType::EXACT,
-        };
-    }
-}
-
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq)]
 pub enum NodeType {
     EXACT,
@@ -36,44 +42,170 @@ pub enum NodeType {
     UPPERBOUND,
 }
 
+// Layout of the data word: best move (16 bits), value (16 bits), depth (8 bits),
+// generation (8 bits) and node type (2 bits)
+fn pack(data: HashData, generation: u8) -> u64 {
+    let node_type: u64 = match data.node_type {
+        NodeType::EXACT => 0,
+        NodeType::LOWERBOUND => 1,
+        NodeType::UPPERBOUND => 2,
+    };
+
+    return data.best_move.raw() as u64
+        | (data.value as u16 as u64) << 16
+        | (data.depth as u64) << 32
+        | (generation as u64) << 40
+        | node_type << 48;
+}
+
+fn unpack(word: u64) -> (HashData, u8) {
+    let data = HashData {
+        best_move: Move::new(word as u16),
+        value: (word >> 16) as u16 as i16,
+        depth: (word >> 32) as u8,
+        node_type: match (word >> 48) & 0b11 {
+            1 => NodeType::LOWERBOUND,
+            2 => NodeType::UPPERBOUND,
+            _ => NodeType::EXACT,
+        },
+    };
+
+    return (data, (word >> 40) as u8);
+}
+
+impl Entry {
+    fn empty() -> Self {
+        return Self {
+            key: AtomicU64::new(0),
+            data: AtomicU64::new(0),
+        };
+    }
+
+    // Key, data and generation of the entry. The key is zero for an empty entry.
+    fn load(&self) -> (u64, HashData, u8) {
+        let word: u64 = self.data.load(Ordering::Relaxed);
+        let (data, generation) = unpack(word);
+
+        return (self.key.load(Ordering::Relaxed) ^ word, data, generation);
+    }
+
+    fn save(&self, key: u64, data: HashData, generation: u8) {
+        let word: u64 = pack(data, generation);
+
+        self.key.store(key ^ word, Ordering::Relaxed);
+        self.data.store(word, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.key.store(0, Ordering::Relaxed);
+        self.data.store(0, Ordering::Relaxed);
+    }
+}
+
+// Entries from older searches and shallower entries are replaced first
+fn replacement_score(data: HashData, entry_generation: u8, generation: u8) -> i16 {
+    let age: u8 = generation.wrapping_sub(entry_generation);
+
+    return data.depth as i16 - 8 * age as i16;
+}
+
 impl TranspositionTable {
     // Size is in MB
     pub fn new(megabytes: usize) -> Self {
         let size = megabytes * 1024 * 1024;
-        let nb_entries = size / std::mem::size_of::<Entry>();
+        let nb_buckets = size / std::mem::size_of::<Bucket>();
 
         return Self {
-            entries: vec![
-                Entry {
-                    key: 0,
-                    data: HashData::default(),
-                };
-                nb_entries
-            ],
-            size: nb_entries,
+            buckets: (0..nb_buckets)
+                .map(|_| Bucket {
+                    entries: std::array::from_fn(|_| Entry::empty()),
+                })
+                .collect(),
+            size: nb_buckets,
+            generation: AtomicU8::new(0),
         };
     }
 
-    pub fn store(&mut self, key: u64, data: HashData) {
-        let index = key % self.size as u64;
-        let entry = &mut self.entries[index as usize];
+    // Should be called before every search so that entries from previous searches age
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
 
-        if entry.key == key {
-            entry.data = data;
-        } else if entry.key == 0 {
-            entry.key = key;
-            entry.data = data;
+    pub fn clear(&self) {
+        for bucket in self.buckets.iter() {
+            for entry in bucket.entries.iter() {
+                entry.clear();
+            }
         }
+
+        self.generation.store(0, Ordering::Relaxed);
     }
 
-    pub fn probe(&self, key: u64) -> Option<&HashData> {
+    pub fn store(&self, key: u64, data: HashData) {
         let index = key % self.size as u64;
-        let entry = &self.entries[index as usize];
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = &self.buckets[index as usize];
+        let entries: [(u64, HashData, u8); ENTRIES_PER_BUCKET] = std::array::from_fn(|i| bucket.entries[i].load());
 
-        if entry.key == key {
-            return Some(&entry.data);
+        let replaced: usize = match entries
+            .iter()
+            .position(|&(entry_key, _, _)| entry_key == key || entry_key == 0)
+        {
+            Some(i) => i,
+            None => {
+                let mut replaced: usize = 0;
+
+                for i in 1..ENTRIES_PER_BUCKET {
+                    if replacement_score(entries[i].1, entries[i].2, generation)
+                        < replacement_score(entries[replaced].1, entries[replaced].2, generation)
+                    {
+                        replaced = i;
+                    }
+                }
+
+                replaced
+            }
+        };
+
+        let (entry_key, entry_data, _) = entries[replaced];
+        let mut data = data;
+
+        // Keep the previous best move if the new search did not find one
+        if entry_key == key && !data.best_move.is_ok() {
+            data.best_move = entry_data.best_move;
         }
 
-        return None;
+        bucket.entries[replaced].save(key, data, generation);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<HashData> {
+        let index = key % self.size as u64;
+        let bucket = &self.buckets[index as usize];
+
+        return bucket
+            .entries
+            .iter()
+            .map(|entry| entry.load())
+            .find(|&(entry_key, _, _)| entry_key == key)
+            .map(|(_, data, _)| data);
+    }
+
+    // Approximation of the table occupancy in permille, only counting entries of the current search
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+
+        return self.buckets[0..HASHFULL_SAMPLE.min(self.size)]
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .entries
+                    .iter()
+                    .map(|entry| entry.load())
+                    .filter(|&(key, _, entry_generation)| key != 0 && entry_generation == generation)
+                    .count()
+            })
+            .sum::<usize>()
+            * 1000
+            / (HASHFULL_SAMPLE.min(self.size) * ENTRIES_PER_BUCKET);
     }
 }
@@ -0,0 +1,219 @@
+use std::fs;
+
+use crate::defs::*;
+
+use super::{defs::Score, tables::*};
+
+// Weights of the terms of the classical evaluation. The constants of tables.rs are the
+// built-in defaults, other weights can be loaded from a file without rebuilding.
+//
+// The file is a subset of TOML: one array of integers per key, the tables being laid out as
+// seen by white with the eighth rank first. Missing keys keep their default values.
+//
+//   piece_values_mg = [82, 337, 365, 477, 1025, 0]    pawn to king
+//   piece_values_eg = [94, 281, 297, 512, 936, 0]
+//   mobility_mg = [4, 5, 2, 1]                        knight to queen, as king_attack
+//   passed_pawn_mg = [0, 5, 5, 10, 20, 40, 70, 0]     by relative rank
+//   doubled_pawn_mg = [-10]                           single values, as the other terms
+//   pawn_mg = [0, 0, 0, 0, 0, 0, 0, 0, 98, 134, ...]  64 values, a8 to h1
+//   pawn_eg, knight_mg, knight_eg, ..., king_eg
+pub const DEFAULT_WEIGHTS: &str = "<default>";
+
+// Largest weights accepted from a file, so that the material of sixteen pieces on their
+// best squares still fits in the sums of the position
+const MAX_PIECE_VALUE: i16 = 1500;
+const MAX_WEIGHT: i16 = 500;
+
+const PIECE_NAMES: [&str; NrOf::PIECE_TYPES] = ["none", "pawn", "knight", "bishop", "rook", "queen", "king"];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Weights {
+    pub piece_values: [Score; NrOf::PIECE_TYPES],
+    pub piece_squares: [[Score; NrOf::SQUARES]; NrOf::PIECE_TYPES],
+    pub mobility: [Score; NrOf::PIECE_TYPES],
+    pub doubled_pawn: Score,
+    pub isolated_pawn: Score,
+    pub backward_pawn: Score,
+    pub passed_pawn: [Score; 8],
+    pub passed_pawn_free: Score,
+    pub king_attack: [Score; NrOf::PIECE_TYPES],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub tempo: Score,
+}
+
+impl Weights {
+    pub fn default() -> Self {
+        let mut weights = Self {
+            piece_values: [Score::ZERO; NrOf::PIECE_TYPES],
+            piece_squares: [[Score::ZERO; NrOf::SQUARES]; NrOf::PIECE_TYPES],
+            mobility: MOBILITY_WEIGHTS,
+            doubled_pawn: DOUBLED_PAWN,
+            isolated_pawn: ISOLATED_PAWN,
+            backward_pawn: BACKWARD_PAWN,
+            passed_pawn: PASSED_PAWN,
+            passed_pawn_free: PASSED_PAWN_FREE,
+            king_attack: KING_ATTACK_WEIGHTS,
+            bishop_pair: BISHOP_PAIR,
+            rook_open_file: ROOK_OPEN_FILE,
+            rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+            tempo: TEMPO,
+        };
+
+        for piece_type in PieceType::PAWN..=PieceType::KING {
+            weights.piece_values[piece_type] = Score::new(PIECE_VALUES_MG[piece_type], PIECE_VALUES_EG[piece_type]);
+
+            for square in RangeOf::SQUARES {
+                weights.piece_squares[piece_type][square] = Score::new(
+                    PIECE_SQUARE_MG_TABLES[piece_type][square],
+                    PIECE_SQUARE_EG_TABLES[piece_type][square],
+                );
+            }
+        }
+
+        return weights;
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Cannot read {}: {}", path, error))?;
+
+        return Weights::from_text(&text);
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        return fs::write(path, self.to_text()).map_err(|error| format!("Cannot write {}: {}", path, error));
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut weights = Weights::default();
+        let content: String = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let mut arrays: Vec<&str> = content.split(']').collect();
+
+        // Only blanks can follow the last array
+        if !arrays.pop().unwrap_or("").trim().is_empty() {
+            return Err("Expected key = [values]".to_string());
+        }
+
+        for array in arrays {
+            let (key, values) = array
+                .split_once('=')
+                .and_then(|(key, values)| Some((key.trim(), values.trim().strip_prefix('[')?)))
+                .ok_or(format!("Expected key = [values] near '{}'", array.trim()))?;
+            let values: Vec<i16> = values
+                .split(',')
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<i16>().map_err(|_| format!("Invalid value '{}' in {}", value, key)))
+                .collect::<Result<Vec<i16>, String>>()?;
+
+            weights.set(key, &values)?;
+        }
+
+        return Ok(weights);
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text: String = String::from("# Oxide evaluation weights\n");
+        // Only a copy can hand out the scores of its fields
+        let mut weights: Weights = self.clone();
+
+        for (key, scores) in weights.fields() {
+            text += "\n";
+
+            for (phase, mg) in [("mg", true), ("eg", false)] {
+                let values: Vec<i16> = scores.iter().map(|score| Weights::phase_value(**score, mg)).collect();
+
+                // The piece square tables are written rank by rank
+                if values.len() == NrOf::SQUARES {
+                    text += &format!("{}_{} = [\n", key, phase);
+
+                    for rank in values.chunks(8) {
+                        let values: Vec<String> = rank.iter().map(|value| format!("{:>4}", value)).collect();
+
+                        text += &format!("    {},\n", values.join(","));
+                    }
+
+                    text += "]\n";
+                } else {
+                    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+
+                    text += &format!("{}_{} = [{}]\n", key, phase, values.join(", "));
+                }
+            }
+        }
+
+        return text;
+    }
+
+    fn set(&mut self, key: &str, values: &[i16]) -> Result<(), String> {
+        let (name, phase) = key.rsplit_once('_').ok_or(format!("Unknown key {}", key))?;
+        let mg: bool = match phase {
+            "mg" => true,
+            "eg" => false,
+            _ => return Err(format!("Unknown key {}", key)),
+        };
+        let limit: i16 = match name {
+            "piece_values" => MAX_PIECE_VALUE,
+            _ => MAX_WEIGHT,
+        };
+
+        if let Some(value) = values.iter().find(|value| !(-limit..=limit).contains(*value)) {
+            return Err(format!("{} out of range in {}, the weights are between -{} and {}", value, key, limit, limit));
+        }
+
+        let scores: Vec<&mut Score> = match self.fields().into_iter().find(|(field, _)| *field == name) {
+            Some((_, scores)) => scores,
+            None => return Err(format!("Unknown key {}", key)),
+        };
+
+        if scores.len() != values.len() {
+            return Err(format!("{} needs {} values, not {}", key, scores.len(), values.len()));
+        }
+
+        for (score, &value) in scores.into_iter().zip(values) {
+            match mg {
+                true => score.mg = value,
+                false => score.eg = value,
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Keys of the file and the scores they hold, in the order they are written. Only the
+    // pieces which have a mobility or attack the king are listed.
+    fn fields(&mut self) -> Vec<(&'static str, Vec<&mut Score>)> {
+        let mut fields: Vec<(&'static str, Vec<&mut Score>)> = vec![
+            ("piece_values", self.piece_values[PieceType::PAWN..=PieceType::KING].iter_mut().collect()),
+            ("mobility", self.mobility[PieceType::KNIGHT..=PieceType::QUEEN].iter_mut().collect()),
+            ("doubled_pawn", vec![&mut self.doubled_pawn]),
+            ("isolated_pawn", vec![&mut self.isolated_pawn]),
+            ("backward_pawn", vec![&mut self.backward_pawn]),
+            ("passed_pawn", self.passed_pawn.iter_mut().collect()),
+            ("passed_pawn_free", vec![&mut self.passed_pawn_free]),
+            ("king_attack", self.king_attack[PieceType::KNIGHT..=PieceType::QUEEN].iter_mut().collect()),
+            ("bishop_pair", vec![&mut self.bishop_pair]),
+            ("rook_open_file", vec![&mut self.rook_open_file]),
+            ("rook_semi_open_file", vec![&mut self.rook_semi_open_file]),
+            ("tempo", vec![&mut self.tempo]),
+        ];
+
+        for (name, table) in PIECE_NAMES.iter().zip(self.piece_squares.iter_mut()).skip(PieceType::PAWN) {
+            fields.push((name, table.iter_mut().collect()));
+        }
+
+        return fields;
+    }
+
+    fn phase_value(score: Score, mg: bool) -> i16 {
+        return match mg {
+            true => score.mg,
+            false => score.eg,
+        };
+    }
+}
@@ -1,7 +1,86 @@
-pub struct Hasher {}
+use crate::defs::*;
+
+// Zobrist keys used to identify a position. The seed is fixed so that
+// every Hasher instance produces the same keys.
+#[derive(Clone)]
+pub struct Hasher {
+    pieces: [[[u64; NrOf::SQUARES]; NrOf::PIECE_TYPES]; NrOf::SIDES],
+    castling: [u64; NrOf::CASTLING_PERMISSIONS],
+    en_passant: [u64; NrOf::FILES],
+    side: u64,
+}
 
 impl Hasher {
     pub fn new() -> Self {
-        Self {}
+        let mut random = Random::new(1070372);
+        let mut hasher = Self {
+            pieces: [[[0u64; NrOf::SQUARES]; NrOf::PIECE_TYPES]; NrOf::SIDES],
+            castling: [0u64; NrOf::CASTLING_PERMISSIONS],
+            en_passant: [0u64; NrOf::FILES],
+            side: 0u64,
+        };
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            // PieceType::NONE keeps a zero key so that empty squares do not alter the hash
+            for piece_type in PieceType::PAWN..=PieceType::KING {
+                for square in RangeOf::SQUARES {
+                    hasher.pieces[side][piece_type][square] = random.next();
+                }
+            }
+        }
+
+        for rights in 0..NrOf::CASTLING_PERMISSIONS {
+            hasher.castling[rights] = random.next();
+        }
+
+        for file in RangeOf::FILES {
+            hasher.en_passant[file] = random.next();
+        }
+
+        hasher.side = random.next();
+
+        return hasher;
+    }
+
+    pub fn piece(&self, piece: Piece, square: Square) -> u64 {
+        return self.pieces[color_of_piece(piece)][type_of_piece(piece)][square];
+    }
+
+    pub fn castling(&self, rights: usize) -> u64 {
+        return self.castling[rights];
+    }
+
+    pub fn en_passant(&self, square: Square) -> u64 {
+        return match square {
+            NONE_SQUARE => 0u64,
+            _ => self.en_passant[file_of(square)],
+        };
+    }
+
+    pub fn side(&self) -> u64 {
+        return self.side;
+    }
+}
+
+// xorshift64* pseudo random number generator
+// See https://vigna.di.unimi.it/ftp/papers/xorshift.pdf
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        #[cfg(debug_assertions)]
+        assert!(seed != 0);
+
+        return Self { state: seed };
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        return self.state.wrapping_mul(2685821657736338717);
     }
-}
\ No newline at end of file
+}
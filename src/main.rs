@@ -1,25 +1,30 @@
+// Explicit returns and explicit field initialization are the style of this code base
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
 mod benchmark;
 mod bitboards;
 mod defs;
 mod evaluate;
+mod hash;
 mod misc;
 mod movegen;
 mod position;
 mod search;
-mod uci;
 mod time;
+mod tune;
+mod uci;
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{bitboards::Bitboards, evaluate::Eval, movegen::Movegen, position::Position, search::Search, uci::UCI};
 
 fn main() {
     println!("Oxide v0.1.0 by Theo Dammaretz");
 
-    let bitboards = Rc::new(Bitboards::new());
-    let movegen = Movegen::new(Rc::clone(&bitboards));
-    let position = Position::new(Rc::clone(&bitboards));
-    let eval = Eval::new();
+    let bitboards = Arc::new(Bitboards::new());
+    let movegen = Movegen::new(Arc::clone(&bitboards));
+    let position = Position::new(Arc::clone(&bitboards));
+    let eval = Eval::new(Arc::clone(&bitboards));
     let mut search = Search::new(position, movegen, eval);
 
     UCI::main_loop(&mut search);
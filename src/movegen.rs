@@ -1,6 +1,6 @@
 pub mod defs;
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     bitboards::{defs::*, Bitboards},
@@ -11,21 +11,31 @@ use crate::{
 
 use self::defs::*;
 
+#[derive(Clone)]
 pub struct Movegen {
-    bitboards: Rc<Bitboards>,
+    bitboards: Arc<Bitboards>,
 }
 
 impl Movegen {
-    pub fn new(bitboards: Rc<Bitboards>) -> Self {
+    pub fn new(bitboards: Arc<Bitboards>) -> Self {
         Self { bitboards }
     }
 
-    pub fn legal_moves(&self, position: &Position) -> Vec<Move> {
+    pub fn legal_moves(&self, position: &Position) -> MoveList {
+        let gen_type = match position.in_check() {
+            true => GenTypes::EVASIONS,
+            false => GenTypes::NON_EVASIONS,
+        };
+
+        return self.legal_moves_of_type(position, gen_type);
+    }
+
+    pub fn legal_moves_of_type(&self, position: &Position, gen_type: GenType) -> MoveList {
         let us = position.side_to_move;
         let king_square: Square = bits::lsb(position.by_type_bb[us][PieceType::KING]);
 
-        let mut movelist = self.generate(position, us);
-        movelist.retain(|&mv| {
+        let mut movelist = self.generate(position, gen_type);
+        movelist.retain(|&mut mv| {
             (position.pinned_bb[us] & square_bb(mv.from_sq()) == EMPTY
                 && king_square != mv.from_sq()
                 && mv.type_of() != MoveTypes::EN_PASSANT)
@@ -35,33 +45,61 @@ impl Movegen {
         return movelist;
     }
 
-    fn generate(&self, position: &Position, us: Side) -> Vec<Move> {
-        let mut movelist: Vec<Move> = Vec::with_capacity(256);
-        let checkers = position.checkers(us);
+    // Pseudo-legal moves, which may leave the king in check and are filtered by Position::legal
+    pub fn generate(&self, position: &Position, gen_type: GenType) -> MoveList {
+        let us: Side = position.side_to_move;
+        let mut movelist = MoveList::new();
+        let checkers_bb: Bitboard = position.checkers_bb();
         let king_square = bits::lsb(position.by_type_bb[us][PieceType::KING]);
-        let target_bb: Bitboard = match checkers.len() {
-            1 => self.bitboards.between_bb[king_square][checkers[0]] | square_bb(checkers[0]),
-            _ => FULL,
+
+        #[cfg(debug_assertions)]
+        assert!(
+            (checkers_bb != EMPTY) == (gen_type == GenTypes::EVASIONS),
+            "Invalid generation type"
+        );
+
+        let target_bb: Bitboard = match gen_type {
+            GenTypes::CAPTURES => position.by_color_bb[us ^ 1],
+            GenTypes::QUIETS => !position.by_color_bb[Sides::BOTH],
+            GenTypes::NON_EVASIONS => !position.by_color_bb[us],
+            // Block the check or capture the checking piece, only the king can escape a double check
+            GenTypes::EVASIONS => match checkers_bb.count_ones() {
+                1 => self.bitboards.between_bb[king_square][bits::lsb(checkers_bb)] | checkers_bb,
+                _ => EMPTY,
+            },
+            _ => panic!("Invalid generation type"),
         };
 
-        if checkers.len() <= 1 {
-            self.generate_pawns(position, &mut movelist, us, target_bb);
+        if target_bb != EMPTY {
+            self.generate_pawns(position, &mut movelist, us, target_bb, gen_type);
             self.generate_piece(position, &mut movelist, PieceType::KNIGHT, us, target_bb);
             self.generate_piece(position, &mut movelist, PieceType::BISHOP, us, target_bb);
             self.generate_piece(position, &mut movelist, PieceType::ROOK, us, target_bb);
             self.generate_piece(position, &mut movelist, PieceType::QUEEN, us, target_bb);
         }
 
-        self.generate_piece(position, &mut movelist, PieceType::KING, us, FULL);
+        let king_target_bb: Bitboard = match gen_type {
+            GenTypes::EVASIONS => !position.by_color_bb[us],
+            _ => target_bb,
+        };
+
+        self.generate_piece(position, &mut movelist, PieceType::KING, us, king_target_bb);
 
-        if checkers.len() == 0 {
+        if gen_type == GenTypes::QUIETS || gen_type == GenTypes::NON_EVASIONS {
             self.generate_castling(position, &mut movelist, us);
         }
 
         return movelist;
     }
 
-    fn generate_pawns(&self, position: &Position, movelist: &mut Vec<Move>, us: Side, target_bb: Bitboard) {
+    fn generate_pawns(
+        &self,
+        position: &Position,
+        movelist: &mut MoveList,
+        us: Side,
+        target_bb: Bitboard,
+        gen_type: GenType,
+    ) {
         let them: Side = us ^ 1;
         let up: Direction = match us {
             Sides::WHITE => Directions::UP,
@@ -82,25 +120,37 @@ impl Movegen {
         let pawns_on_rank_7: Bitboard = position.by_type_bb[us][PieceType::PAWN] & rank_7bb;
         let pawns_outside_rank_7: Bitboard = position.by_type_bb[us][PieceType::PAWN] & !rank_7bb;
         let piece = make_piece(us, PieceType::PAWN);
+        // Promotions are generated with captures, including the ones pushing to an empty square
+        let push_target_bb: Bitboard = match gen_type {
+            GenTypes::CAPTURES => empty_squares,
+            _ => target_bb & empty_squares,
+        };
+        let capture_target_bb: Bitboard = target_bb & position.by_color_bb[them];
+
+        if gen_type != GenTypes::CAPTURES {
+            let mut single_bb: Bitboard = shift(pawns_outside_rank_7, up) & empty_squares;
+            // We generate double pawn pushes from the first push to take blockers on the 3rd rank into account
+            let mut double_bb: Bitboard = shift(single_bb & rank_3bb, up) & push_target_bb;
+            // Then we filter with the target squares
+            single_bb &= push_target_bb;
+
+            while single_bb != EMPTY {
+                let to: Square = bits::pop(&mut single_bb);
+                movelist.push(Move::with_from_to((to as isize - up) as usize, to))
+            }
 
-        let mut single_bb: Bitboard = shift(pawns_outside_rank_7, up) & empty_squares;
-        // We generate double pawn pushes from the first push to take blockers on the 3rd rank into account
-        let mut double_bb: Bitboard = shift(single_bb & rank_3bb, up) & empty_squares & target_bb;
-        // Then we filter with the target squares
-        single_bb = single_bb & target_bb;
-
-        while single_bb != EMPTY {
-            let to: Square = bits::pop(&mut single_bb);
-            movelist.push(Move::with_from_to((to as isize - up) as usize, to))
+            while double_bb != EMPTY {
+                let to: Square = bits::pop(&mut double_bb);
+                movelist.push(Move::with_from_to((to as isize - up - up) as usize, to))
+            }
         }
 
-        while double_bb != EMPTY {
-            let to: Square = bits::pop(&mut double_bb);
-            movelist.push(Move::with_from_to((to as isize - up - up) as usize, to))
+        if gen_type == GenTypes::QUIETS {
+            return;
         }
 
         if pawns_on_rank_7 != EMPTY {
-            let mut promotion_bb: Bitboard = shift(pawns_on_rank_7, up) & empty_squares & target_bb;
+            let mut promotion_bb: Bitboard = shift(pawns_on_rank_7, up) & push_target_bb;
 
             while promotion_bb != EMPTY {
                 let to: Square = bits::pop(&mut promotion_bb);
@@ -114,8 +164,7 @@ impl Movegen {
 
             while attackers_bb != EMPTY {
                 let from: Square = bits::pop(&mut attackers_bb);
-                let mut attack_bb: Bitboard =
-                    self.bitboards.attack_bb(piece, from, EMPTY) & (position.by_color_bb[them]) & target_bb;
+                let mut attack_bb: Bitboard = self.bitboards.attack_bb(piece, from, EMPTY) & capture_target_bb;
 
                 while attack_bb != EMPTY {
                     let to: Square = bits::pop(&mut attack_bb);
@@ -129,16 +178,23 @@ impl Movegen {
 
         // Pawns on rank 7 are already handled above
         let mut attackers_bb: Bitboard = pawns_outside_rank_7;
+        let state = position.states.last().unwrap();
+        let en_passant_bb: Bitboard = match state.en_passant_square {
+            NONE_SQUARE => EMPTY,
+            // When in check, the en passant capture must either take the checking pawn or block the check
+            square
+                if gen_type == GenTypes::EVASIONS
+                    && target_bb & (square_bb(square) | square_bb((square as isize - up) as usize)) == EMPTY =>
+            {
+                EMPTY
+            }
+            square => square_bb(square),
+        };
 
         while attackers_bb != EMPTY {
             let from: Square = bits::pop(&mut attackers_bb);
-            let state = position.states.last().unwrap();
-            let en_passant_bb: Bitboard = match state.en_passant_square {
-                NONE_SQUARE => EMPTY,
-                square => square_bb(square),
-            };
             let mut attack_bb: Bitboard =
-                self.bitboards.attack_bb(piece, from, EMPTY) & (position.by_color_bb[them] | en_passant_bb) & target_bb;
+                self.bitboards.attack_bb(piece, from, EMPTY) & (capture_target_bb | en_passant_bb);
 
             while attack_bb != EMPTY {
                 let to: Square = bits::pop(&mut attack_bb);
@@ -155,7 +211,7 @@ impl Movegen {
     fn generate_piece(
         &self,
         position: &Position,
-        movelist: &mut Vec<Move>,
+        movelist: &mut MoveList,
         piece: Piece,
         us: Side,
         target_bb: Bitboard,
@@ -182,7 +238,7 @@ impl Movegen {
         }
     }
 
-    fn generate_castling(&self, position: &Position, movelist: &mut Vec<Move>, us: Side) {
+    fn generate_castling(&self, position: &Position, movelist: &mut MoveList, us: Side) {
         let king_square = bits::lsb(position.by_type_bb[us][PieceType::KING]);
         let mut rights = position.castling_masks[king_square] & position.states.last().unwrap().castling_rights;
 
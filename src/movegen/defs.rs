@@ -1,5 +1,7 @@
 use std::fmt;
 
+use arrayvec::ArrayVec;
+
 use crate::defs::*;
 
 pub type MoveType = u16;
@@ -12,6 +14,24 @@ impl MoveTypes {
     pub const CASTLING: u16 = 0b11 << 14;
 }
 
+// Captures also include promotions, as they change the material balance. Evasions
+// are only generated when in check, and the other types only when not in check.
+pub type GenType = usize;
+pub struct GenTypes {}
+
+impl GenTypes {
+    pub const CAPTURES: GenType = 0;
+    pub const QUIETS: GenType = 1;
+    pub const EVASIONS: GenType = 2;
+    pub const NON_EVASIONS: GenType = 3;
+}
+
+// No position has more legal moves than this
+pub const MAX_MOVES: usize = 256;
+
+// Lives on the stack so that generating moves does not allocate
+pub type MoveList = ArrayVec<Move, MAX_MOVES>;
+
 pub type CastlingRight = usize;
 pub struct CastlingRights {}
 impl CastlingRights {
@@ -47,19 +67,18 @@ impl Move {
 
     pub fn make(from: Square, to: Square, promotion_type: Piece, movetype: MoveType) -> Self {
         let promotion_value = match promotion_type {
-            PieceType::KNIGHT => PieceType::KNIGHT - PieceType::KNIGHT,
-            PieceType::BISHOP => PieceType::BISHOP - PieceType::KNIGHT,
-            PieceType::ROOK => PieceType::ROOK - PieceType::KNIGHT,
-            PieceType::QUEEN => PieceType::QUEEN - PieceType::KNIGHT,
+            PieceType::KNIGHT..=PieceType::QUEEN => promotion_type - PieceType::KNIGHT,
             _ => 0,
         };
         return Self::new(movetype + (promotion_value << 12) as u16 + (from << 6) as u16 + to as u16);
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn from_sq(&self) -> Square {
         return (self.data >> 6) as Square & 0b111111;
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_sq(&self) -> Square {
         return (self.data & 0b111111) as Square;
     }
@@ -75,6 +94,10 @@ impl Move {
         return ((self.data >> 12) & 0b11) as usize + PieceType::KNIGHT;
     }
 
+    pub fn raw(&self) -> u16 {
+        return self.data;
+    }
+
     pub fn is_ok(&self) -> bool {
         return Self::none().data != self.data && Self::null().data != self.data;
     }
@@ -89,10 +112,10 @@ impl Move {
 }
 
 impl fmt::Debug for Move {
+    // Castling destinations are written as the rook square and the king offset
+    #[allow(clippy::identity_op)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.data == 0 {
-            return write!(f, "0000");
-        } else if self.data == 65 {
+        if !self.is_ok() {
             return write!(f, "0000");
         }
 
@@ -1,12 +1,18 @@
 pub mod defs;
 mod fen;
+mod see;
 mod test;
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::bitboards::defs::EMPTY;
 use crate::bitboards::Bitboards;
 use crate::defs::*;
+use crate::evaluate::defs::Score;
+use crate::evaluate::nnue::{Accumulator, Network};
+use crate::evaluate::tables::GAME_PHASE_INCREMENT;
+use crate::evaluate::weights::Weights;
+use crate::hash::Hasher;
 use crate::misc::bits;
 use crate::movegen::defs::{pawn_push, CastlingRight, CastlingRights, Move, MoveType, MoveTypes};
 
@@ -22,11 +28,17 @@ pub struct Position {
     pub states: Vec<StateInfo>,
     pub castling_masks: [CastlingRight; NrOf::SQUARES],
     pub zobrist: u64,
-    bitboards: Rc<Bitboards>,
+    bitboards: Arc<Bitboards>,
+    hasher: Hasher,
+    // Piece values and piece square tables, summed up in the states
+    weights: Arc<Weights>,
+    // Hidden layer of the network, updated with the pieces when a network is set
+    network: Option<Arc<Network>>,
+    accumulator: Accumulator,
 }
 
 impl Position {
-    pub fn new(bitboards: Rc<Bitboards>) -> Self {
+    pub fn new(bitboards: Arc<Bitboards>) -> Self {
         return Self {
             bitboards: bitboards,
             by_type_bb: [[EMPTY; NrOf::PIECE_TYPES]; NrOf::SIDES],
@@ -37,6 +49,60 @@ impl Position {
             states: vec![StateInfo::new()],
             castling_masks: Position::castling_masks(),
             zobrist: 0u64,
+            hasher: Hasher::new(),
+            weights: Arc::new(Weights::default()),
+            network: None,
+            accumulator: Accumulator::empty(),
+        };
+    }
+
+    // The previous states are brought up to date as the moves are undone
+    pub fn set_weights(&mut self, weights: Arc<Weights>) {
+        self.weights = weights;
+
+        let (psq, phase) = self.compute_material();
+        let state: &mut StateInfo = self.states.last_mut().unwrap();
+        state.psq = psq;
+        state.phase = phase;
+    }
+
+    pub fn weights(&self) -> &Weights {
+        return &self.weights;
+    }
+
+    pub fn bitboards(&self) -> &Bitboards {
+        return &self.bitboards;
+    }
+
+    // Setting no network goes back to the classical evaluation
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+        self.refresh_accumulator();
+    }
+
+    pub fn network(&self) -> Option<&Network> {
+        return self.network.as_deref();
+    }
+
+    pub fn accumulator(&self) -> &Accumulator {
+        return &self.accumulator;
+    }
+
+    // Computes the accumulator from scratch, from the pieces on the board
+    fn refresh_accumulator(&mut self) {
+        self.accumulator = match &self.network {
+            Some(network) => {
+                let mut accumulator: Accumulator = network.new_accumulator();
+                let mut occupied_bb: Bitboard = self.by_color_bb[Sides::BOTH];
+
+                while occupied_bb != EMPTY {
+                    let square: Square = bits::pop(&mut occupied_bb);
+                    network.add(&mut accumulator, self.piece_on(square), square);
+                }
+
+                accumulator
+            }
+            None => Accumulator::empty(),
         };
     }
 
@@ -59,6 +125,9 @@ impl Position {
         };
         let mut new_state = *self.states.last().unwrap();
 
+        // The pieces update the material of the new state as they are moved
+        self.states.push(new_state);
+
         #[cfg(debug_assertions)]
         {
             assert!(color_of_piece(piece) == us);
@@ -75,6 +144,18 @@ impl Position {
             };
 
             self.remove_piece(captured, captured_square);
+
+            if type_of_piece(captured) == PieceType::PAWN {
+                new_state.pawn_key ^= self.hasher.piece(captured, captured_square);
+            }
+        }
+
+        if type_of_piece(piece) == PieceType::PAWN {
+            new_state.pawn_key ^= self.hasher.piece(piece, from);
+
+            if move_type != MoveTypes::PROMOTION {
+                new_state.pawn_key ^= self.hasher.piece(piece, to);
+            }
         }
 
         if move_type == MoveTypes::PROMOTION {
@@ -89,45 +170,111 @@ impl Position {
             self.move_piece(piece, from, to);
         }
 
+        self.zobrist ^= self.hasher.castling(new_state.castling_rights);
         new_state.castling_rights &= !self.castling_masks[from];
         new_state.castling_rights &= !self.castling_masks[to];
+        self.zobrist ^= self.hasher.castling(new_state.castling_rights);
 
         for side in [us, them] {
             self.pinned_bb[side] = self.pinned_bb(side);
         }
 
+        self.zobrist ^= self.hasher.en_passant(new_state.en_passant_square);
+        new_state.en_passant_square = NONE_SQUARE;
+
         if type_of_piece(piece) == PieceType::PAWN && distance(from, to) == 2 {
-            new_state.en_passant_square = match us {
-                Sides::WHITE => from + 8,
-                Sides::BLACK => from - 8,
-                _ => panic!("Invalid side"),
-            };
-        } else {
-            new_state.en_passant_square = 0;
+            let en_passant_square: Square = (to as isize - pawn_push(us)) as usize;
+
+            // The en passant square is only recorded when it can be captured, otherwise
+            // positions that only differ by an unusable en passant square would get different keys.
+            if self.bitboards.attack_bb(piece, en_passant_square, EMPTY) & self.by_type_bb[them][PieceType::PAWN]
+                != EMPTY
+            {
+                new_state.en_passant_square = en_passant_square;
+                self.zobrist ^= self.hasher.en_passant(en_passant_square);
+            }
         }
 
         self.side_to_move = them;
+        self.zobrist ^= self.hasher.side();
         new_state.captured_piece = captured;
-        new_state.rule50 = match captured == PieceType::NONE {
+        new_state.rule50 = match captured == PieceType::NONE && type_of_piece(piece) != PieceType::PAWN {
             true => new_state.rule50 + 1,
             false => 0,
         };
         new_state.game_ply += 1;
+        new_state.plies_from_null += 1;
+        new_state.key = self.zobrist;
+        new_state.checkers_bb = self.compute_checkers_bb();
+
+        let state: &mut StateInfo = self.states.last_mut().unwrap();
+        new_state.psq = state.psq;
+        new_state.phase = state.phase;
+        *state = new_state;
+
+        #[cfg(debug_assertions)]
+        {
+            assert_eq!(self.zobrist, self.compute_key(), "Zobrist key mismatch after {:?}", mv);
+            assert_eq!(
+                new_state.pawn_key,
+                self.compute_pawn_key(),
+                "Pawn key mismatch after {:?}",
+                mv
+            );
+            assert_eq!(
+                (new_state.psq, new_state.phase),
+                self.compute_material(),
+                "Material mismatch after {:?}",
+                mv
+            );
+        }
+    }
+
+    // Passes the turn to the opponent, which should not be done when in check
+    pub fn do_null_move(&mut self) {
+        let mut new_state = *self.states.last().unwrap();
+
+        #[cfg(debug_assertions)]
+        assert!(!self.in_check());
+
+        // The en passant capture is no longer possible after passing
+        self.zobrist ^= self.hasher.en_passant(new_state.en_passant_square);
+        new_state.en_passant_square = NONE_SQUARE;
+
+        self.side_to_move ^= 1;
+        self.zobrist ^= self.hasher.side();
+        new_state.captured_piece = PieceType::NONE;
+        new_state.rule50 += 1;
+        new_state.game_ply += 1;
+        new_state.plies_from_null = 0;
+        new_state.key = self.zobrist;
+        // The opponent cannot be in check since we were not
+        new_state.checkers_bb = EMPTY;
         self.states.push(new_state);
+
+        #[cfg(debug_assertions)]
+        assert_eq!(self.zobrist, self.compute_key(), "Zobrist key mismatch after null move");
+    }
+
+    pub fn undo_null_move(&mut self) {
+        self.states.pop();
+        self.side_to_move ^= 1;
+        self.zobrist = self.states.last().unwrap().key;
     }
 
     pub fn undo_move(&mut self, mv: Move) {
         #[cfg(debug_assertions)]
         assert!(mv.is_ok());
 
-        self.side_to_move = self.side_to_move ^ 1;
+        self.side_to_move ^= 1;
         let us: Side = self.side_to_move;
         let them: Side = self.side_to_move ^ 1;
         let from: Square = mv.from_sq();
         let to: Square = mv.to_sq();
         let mut piece: Piece = self.piece_on(to);
         let move_type: MoveType = mv.type_of();
-        let last_state: StateInfo = self.states.pop().unwrap();
+        // Popped once the pieces are back, so that they do not update the previous state
+        let last_state: StateInfo = *self.states.last().unwrap();
 
         #[cfg(debug_assertions)]
         {
@@ -167,6 +314,61 @@ impl Position {
         for side in [them, us] {
             self.pinned_bb[side] = self.pinned_bb(side);
         }
+
+        // Moving the pieces back restored the material of the previous position with the
+        // current weights, the previous state may have been computed with older ones
+        let undone: StateInfo = self.states.pop().unwrap();
+        let state: &mut StateInfo = self.states.last_mut().unwrap();
+        state.psq = undone.psq;
+        state.phase = undone.phase;
+
+        let state: &StateInfo = self.states.last().unwrap();
+        self.zobrist ^= self.hasher.side();
+        self.zobrist ^= self.hasher.castling(last_state.castling_rights) ^ self.hasher.castling(state.castling_rights);
+        self.zobrist ^=
+            self.hasher.en_passant(last_state.en_passant_square) ^ self.hasher.en_passant(state.en_passant_square);
+
+        #[cfg(debug_assertions)]
+        {
+            assert_eq!(self.zobrist, state.key, "Zobrist key mismatch after undoing {:?}", mv);
+            assert_eq!(
+                self.zobrist,
+                self.compute_key(),
+                "Zobrist key mismatch after undoing {:?}",
+                mv
+            );
+            assert_eq!(
+                (state.psq, state.phase),
+                self.compute_material(),
+                "Material mismatch after undoing {:?}",
+                mv
+            );
+        }
+    }
+
+    // Repetitions of a position reached after the root of the search are draws right away,
+    // while positions of the game history need to be repeated twice (threefold repetition).
+    // Only positions since the last irreversible move or null move can be repeated.
+    pub fn is_repetition(&self, ply: usize) -> bool {
+        let state: &StateInfo = self.states.last().unwrap();
+        let end: usize = state.rule50.min(state.plies_from_null).min(self.states.len() - 1);
+        let mut repetitions: usize = 0;
+
+        for distance in (4..=end).step_by(2) {
+            if self.states[self.states.len() - 1 - distance].key == state.key {
+                if distance < ply {
+                    return true;
+                }
+
+                repetitions += 1;
+
+                if repetitions == 2 {
+                    return true;
+                }
+            }
+        }
+
+        return false;
     }
 
     pub fn piece_on(&self, square: Square) -> Piece {
@@ -176,6 +378,23 @@ impl Position {
         return self.board[square];
     }
 
+    // Without pieces other than pawns, passing is often the best move (zugzwang)
+    pub fn has_non_pawn_material(&self, side: Side) -> bool {
+        return self.by_color_bb[side]
+            & !self.by_type_bb[side][PieceType::PAWN]
+            & !self.by_type_bb[side][PieceType::KING]
+            != EMPTY;
+    }
+
+    // Castling is encoded as the king capturing its own rook, so it is not a capture
+    pub fn is_capture(&self, mv: Move) -> bool {
+        return match mv.type_of() {
+            MoveTypes::EN_PASSANT => true,
+            MoveTypes::CASTLING => false,
+            _ => self.piece_on(mv.to_sq()) != PieceType::NONE,
+        };
+    }
+
     fn put_piece(&mut self, piece: Piece, square: Square) {
         #[cfg(debug_assertions)]
         assert!(piece < 18);
@@ -188,6 +407,16 @@ impl Position {
         self.by_type_bb[Sides::BOTH][type_of_piece(piece)] |= bb;
         self.by_color_bb[side] |= bb;
         self.by_color_bb[Sides::BOTH] |= bb;
+        self.zobrist ^= self.hasher.piece(piece, square);
+
+        let psq: Score = self.psq(piece, square);
+        let state: &mut StateInfo = self.states.last_mut().unwrap();
+        state.psq[side] += psq;
+        state.phase[side] += GAME_PHASE_INCREMENT[type_of_piece(piece)];
+
+        if let Some(network) = &self.network {
+            network.add(&mut self.accumulator, piece, square);
+        }
     }
 
     fn remove_piece(&mut self, piece: Piece, square: Square) {
@@ -202,6 +431,20 @@ impl Position {
         self.by_type_bb[Sides::BOTH][type_of_piece(piece)] &= !bb;
         self.by_color_bb[side] &= !bb;
         self.by_color_bb[Sides::BOTH] &= !bb;
+        self.zobrist ^= self.hasher.piece(piece, square);
+
+        // Empty squares have no value
+        let psq: Score = self.psq(piece, square);
+        let state: &mut StateInfo = self.states.last_mut().unwrap();
+        state.psq[side] -= psq;
+        state.phase[side] -= GAME_PHASE_INCREMENT[type_of_piece(piece)];
+
+        // Clearing the board removes the empty squares too
+        if piece != PieceType::NONE {
+            if let Some(network) = &self.network {
+                network.remove(&mut self.accumulator, piece, square);
+            }
+        }
     }
 
     // This function is only for moving and does not handle captures
@@ -225,6 +468,25 @@ impl Position {
         self.by_type_bb[Sides::BOTH][type_of_piece(piece)] ^= bb_from | bb_to;
         self.by_color_bb[side] ^= bb_from | bb_to;
         self.by_color_bb[Sides::BOTH] ^= bb_from | bb_to;
+        self.zobrist ^= self.hasher.piece(piece, from) ^ self.hasher.piece(piece, to);
+
+        let psq: Score = self.psq(piece, to) - self.psq(piece, from);
+        self.states.last_mut().unwrap().psq[side] += psq;
+
+        if let Some(network) = &self.network {
+            network.move_piece(&mut self.accumulator, piece, from, to);
+        }
+    }
+
+    // Piece value and piece square value, the tables being laid out as seen by white
+    fn psq(&self, piece: Piece, square: Square) -> Score {
+        let piece_type: Piece = type_of_piece(piece);
+        let index: Square = match color_of_piece(piece) {
+            Sides::WHITE => square ^ 56,
+            _ => square,
+        };
+
+        return self.weights.piece_values[piece_type] + self.weights.piece_squares[piece_type][index];
     }
 
     fn castle(&mut self, side: Side, from: Square, to: Square, undo: bool) {
@@ -268,41 +530,139 @@ impl Position {
         self.states = vec![StateInfo::new()];
         self.castling_masks = Position::castling_masks();
         self.states = vec![StateInfo::new()];
+        self.zobrist = 0u64;
     }
 
-    pub fn checkers(&self, defending_side: Side) -> Vec<Square> {
-        #[cfg(debug_assertions)]
-        assert!(defending_side == Sides::WHITE || defending_side == Sides::BLACK);
+    // Computes the material from scratch, used to verify the incremental updates
+    fn compute_material(&self) -> ([Score; NrOf::SIDES], [i16; NrOf::SIDES]) {
+        let mut psq: [Score; NrOf::SIDES] = [Score::ZERO; NrOf::SIDES];
+        let mut phase: [i16; NrOf::SIDES] = [0; NrOf::SIDES];
+        let mut occupied_bb: Bitboard = self.by_color_bb[Sides::BOTH];
 
-        let mut checkers: Vec<Square> = Vec::new();
-        let mut attackers_bb: Bitboard = self.by_color_bb[defending_side ^ 1];
-        let kind_bb: Bitboard = self.by_type_bb[defending_side][PieceType::KING];
+        while occupied_bb != EMPTY {
+            let square: Square = bits::pop(&mut occupied_bb);
+            let piece: Piece = self.piece_on(square);
 
-        while attackers_bb != EMPTY {
-            let square: Square = bits::pop(&mut attackers_bb);
-            let attack_bb: Bitboard =
-                self.bitboards
-                    .attack_bb(self.piece_on(square), square, self.by_color_bb[Sides::BOTH]);
+            psq[color_of_piece(piece)] += self.psq(piece, square);
+            phase[color_of_piece(piece)] += GAME_PHASE_INCREMENT[type_of_piece(piece)];
+        }
+
+        return (psq, phase);
+    }
 
-            if attack_bb & kind_bb != EMPTY {
-                checkers.push(square);
+    // Computes the Zobrist key from scratch, used to verify the incremental updates
+    #[cfg(debug_assertions)]
+    fn compute_key(&self) -> u64 {
+        let state: &StateInfo = self.states.last().unwrap();
+        let mut key: u64 =
+            self.hasher.castling(state.castling_rights) ^ self.hasher.en_passant(state.en_passant_square);
+        let mut occupied_bb: Bitboard = self.by_color_bb[Sides::BOTH];
+
+        while occupied_bb != EMPTY {
+            let square: Square = bits::pop(&mut occupied_bb);
+            key ^= self.hasher.piece(self.piece_on(square), square);
+        }
+
+        if self.side_to_move == Sides::BLACK {
+            key ^= self.hasher.side();
+        }
+
+        return key;
+    }
+
+    fn compute_pawn_key(&self) -> u64 {
+        let mut key: u64 = 0;
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            let mut pawns: Bitboard = self.by_type_bb[side][PieceType::PAWN];
+
+            while pawns != EMPTY {
+                key ^= self
+                    .hasher
+                    .piece(make_piece(side, PieceType::PAWN), bits::pop(&mut pawns));
             }
         }
 
-        return checkers;
+        return key;
+    }
+
+    pub fn pawn_key(&self) -> u64 {
+        return self.states.last().unwrap().pawn_key;
     }
 
-    fn attacks_bb(&self, side: Side, occupied: Bitboard) -> Bitboard {
-        let mut attacks_bb: Bitboard = EMPTY;
-        let mut opponents: Bitboard = self.by_color_bb[side];
+    // Enemy pieces giving check to the side to move, cached when making a move
+    pub fn checkers_bb(&self) -> Bitboard {
+        return self.states.last().unwrap().checkers_bb;
+    }
+
+    pub fn in_check(&self) -> bool {
+        return self.checkers_bb() != EMPTY;
+    }
+
+    fn compute_checkers_bb(&self) -> Bitboard {
+        let us: Side = self.side_to_move;
+        let king_square: Square = bits::lsb(self.by_type_bb[us][PieceType::KING]);
 
-        while opponents != EMPTY {
-            let square: Square = bits::pop(&mut opponents);
+        return self.attackers_to(king_square, self.by_color_bb[Sides::BOTH]) & self.by_color_bb[us ^ 1];
+    }
+
+    // Whether the move, assumed legal, gives check to the opponent, directly or by
+    // uncovering a slider. The position after the move is only computed as bitboards.
+    pub fn gives_check(&self, mv: Move) -> bool {
+        let us: Side = self.side_to_move;
+        let from: Square = mv.from_sq();
+        let to: Square = mv.to_sq();
+        let king_bb: Bitboard = self.by_type_bb[us ^ 1][PieceType::KING];
+        let king_square: Square = bits::lsb(king_bb);
+        let mut occupied: Bitboard = self.by_color_bb[Sides::BOTH] ^ square_bb(from);
+        // Our pieces which left their square and cannot uncover a check anymore
+        let mut moved_bb: Bitboard = square_bb(from);
+
+        let (piece, destination): (Piece, Square) = match mv.type_of() {
+            MoveTypes::CASTLING => {
+                // The king cannot give check, only the rook can
+                let (king_to, rook_to) = match to > from {
+                    true => (from + 2, to - 2),
+                    false => (from - 2, to + 3),
+                };
+
+                occupied = (occupied ^ square_bb(to)) | square_bb(king_to) | square_bb(rook_to);
+                moved_bb |= square_bb(to);
+                (make_piece(us, PieceType::ROOK), rook_to)
+            }
+            MoveTypes::EN_PASSANT => {
+                occupied = (occupied ^ square_bb((to as isize - pawn_push(us)) as usize)) | square_bb(to);
+                (self.piece_on(from), to)
+            }
+            MoveTypes::PROMOTION => {
+                occupied |= square_bb(to);
+                (make_piece(us, mv.promotion_type()), to)
+            }
+            _ => {
+                occupied |= square_bb(to);
+                (self.piece_on(from), to)
+            }
+        };
 
-            attacks_bb |= self.bitboards.attack_bb(self.piece_on(square), square, occupied);
+        // Direct check from the moving piece, or the rook when castling
+        if type_of_piece(piece) != PieceType::KING
+            && self.bitboards.attack_bb(piece, destination, occupied) & king_bb != EMPTY
+        {
+            return true;
         }
 
-        return attacks_bb;
+        // Discovered check from a slider which was behind the moving piece or the captured pawn
+        let queens_bb: Bitboard = self.by_type_bb[us][PieceType::QUEEN];
+        let bishops_bb: Bitboard = (self.by_type_bb[us][PieceType::BISHOP] | queens_bb) & !moved_bb;
+        let rooks_bb: Bitboard = (self.by_type_bb[us][PieceType::ROOK] | queens_bb) & !moved_bb;
+
+        return self.bitboards.attack_bb(PieceType::BISHOP, king_square, occupied) & bishops_bb != EMPTY
+            || self.bitboards.attack_bb(PieceType::ROOK, king_square, occupied) & rooks_bb != EMPTY;
+    }
+
+    fn is_attacked(&self, square: Square, side: Side, occupied: Bitboard) -> bool {
+        // Pieces missing from the occupancy are considered captured and do not attack
+        return self.attackers_to(square, occupied) & self.by_color_bb[side] & occupied != EMPTY;
     }
 
     fn pinned_bb(&self, side: Side) -> Bitboard {
@@ -330,6 +690,98 @@ impl Position {
         return pinned_bb;
     }
 
+    // Whether the move could have been generated in this position, so that moves coming
+    // from the transposition table or the killers can be validated without generating
+    // all the moves. Position::legal then tells whether it leaves the king in check.
+    pub fn is_pseudo_legal(&self, mv: Move) -> bool {
+        if !mv.is_ok() {
+            return false;
+        }
+
+        let us: Side = self.side_to_move;
+        let them: Side = us ^ 1;
+        let from: Square = mv.from_sq();
+        let to: Square = mv.to_sq();
+        let piece: Piece = self.piece_on(from);
+        let state: &StateInfo = self.states.last().unwrap();
+        let up: Direction = pawn_push(us);
+        let (second_rank, last_rank): (usize, usize) = match us {
+            Sides::WHITE => (1, 7),
+            _ => (6, 0),
+        };
+
+        // Reject moves with unused bits set, they would never be generated
+        if piece == PieceType::NONE
+            || color_of_piece(piece) != us
+            || Move::make(from, to, mv.promotion_type(), mv.type_of()) != mv
+        {
+            return false;
+        }
+
+        match mv.type_of() {
+            // The path of the king is checked by Position::legal
+            MoveTypes::CASTLING => {
+                return type_of_piece(piece) == PieceType::KING
+                    && self.piece_on(to) == make_piece(us, PieceType::ROOK)
+                    && self.castling_masks[from] & self.castling_masks[to] & state.castling_rights != 0
+                    && !self.in_check();
+            }
+            // Position::legal fully verifies en passant captures, including when in check
+            MoveTypes::EN_PASSANT => {
+                return type_of_piece(piece) == PieceType::PAWN
+                    && to == state.en_passant_square
+                    && self.bitboards.attack_bb(piece, from, EMPTY) & square_bb(to) != EMPTY;
+            }
+            MoveTypes::PROMOTION => {
+                if type_of_piece(piece) != PieceType::PAWN || rank_of(to) != last_rank {
+                    return false;
+                }
+            }
+            _ => {
+                if type_of_piece(piece) == PieceType::PAWN && rank_of(to) == last_rank {
+                    return false;
+                }
+            }
+        }
+
+        if self.by_color_bb[us] & square_bb(to) != EMPTY {
+            return false;
+        }
+
+        if type_of_piece(piece) == PieceType::PAWN {
+            let single_push: Square = (from as isize + up) as usize;
+            let is_capture =
+                self.bitboards.attack_bb(piece, from, EMPTY) & self.by_color_bb[them] & square_bb(to) != EMPTY;
+            let is_single_push = to == single_push && self.piece_on(to) == PieceType::NONE;
+            let is_double_push = to as isize == from as isize + 2 * up
+                && rank_of(from) == second_rank
+                && self.piece_on(single_push) == PieceType::NONE
+                && self.piece_on(to) == PieceType::NONE;
+
+            if !is_capture && !is_single_push && !is_double_push {
+                return false;
+            }
+        } else if self.bitboards.attack_bb(piece, from, self.by_color_bb[Sides::BOTH]) & square_bb(to) == EMPTY {
+            return false;
+        }
+
+        // Position::legal assumes that moves other than the king's get out of check
+        let checkers_bb: Bitboard = self.checkers_bb();
+
+        if checkers_bb != EMPTY && type_of_piece(piece) != PieceType::KING {
+            let king_square: Square = bits::lsb(self.by_type_bb[us][PieceType::KING]);
+
+            if checkers_bb.count_ones() > 1
+                || (self.bitboards.between_bb[king_square][bits::lsb(checkers_bb)] | checkers_bb) & square_bb(to)
+                    == EMPTY
+            {
+                return false;
+            }
+        }
+
+        return true;
+    }
+
     pub fn legal(&self, mv: Move) -> bool {
         let us: Side = self.side_to_move;
         let them: Side = us ^ 1;
@@ -359,27 +811,32 @@ impl Position {
                 assert!(self.piece_on(from) == make_piece(us, PieceType::PAWN));
             }
 
-            return self.attacks_bb(them, occupied) & self.by_type_bb[us][PieceType::KING] == EMPTY;
+            return !self.is_attacked(bits::lsb(self.by_type_bb[us][PieceType::KING]), them, occupied);
         }
 
         // Castling moves generation does not check if the castling path is clear of
         // enemy attacks, it is delayed at a later time: now!
         if move_type == MoveTypes::CASTLING {
             let between_bb = self.bitboards.between_bb[from][to];
+            let mut path_bb: Bitboard = between_bb & CASTLING_DESTINATION_BB;
 
-            if between_bb & CASTLING_DESTINATION_BB & self.attacks_bb(them, self.by_color_bb[Sides::BOTH]) != EMPTY
-                || between_bb & self.by_color_bb[Sides::BOTH] != EMPTY
-            {
+            if between_bb & self.by_color_bb[Sides::BOTH] != EMPTY {
                 return false;
             }
 
+            while path_bb != EMPTY {
+                if self.is_attacked(bits::pop(&mut path_bb), them, self.by_color_bb[Sides::BOTH]) {
+                    return false;
+                }
+            }
+
             return true;
         }
 
         // If the moving piece is a king, check whether the destination square is
         // attacked by the opponent.
         if type_of_piece(piece) == PieceType::KING {
-            return (self.attacks_bb(them, self.by_color_bb[Sides::BOTH] ^ square_bb(from))) & square_bb(to) == EMPTY;
+            return !self.is_attacked(to, them, self.by_color_bb[Sides::BOTH] ^ square_bb(from));
         }
 
         // A non-king move is legal if and only if it is not pinned or it
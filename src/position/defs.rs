@@ -1,4 +1,4 @@
-use crate::{defs::*, movegen::defs::CastlingRights};
+use crate::{defs::*, evaluate::defs::Score, movegen::defs::CastlingRights};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct StateInfo {
@@ -8,6 +8,15 @@ pub struct StateInfo {
     pub castling_rights: usize,
     pub rule50: usize,
     pub game_ply: usize,
+    pub plies_from_null: usize,
+    // Recomputed when making a move
+    pub key: u64,
+    // Only follows the pawns, identifies the pawn structure
+    pub pawn_key: u64,
+    pub checkers_bb: Bitboard,
+    // Updated by the pieces as they are moved: material and piece square values, and game phase of each side
+    pub psq: [Score; NrOf::SIDES],
+    pub phase: [i16; NrOf::SIDES],
 }
 
 impl StateInfo {
@@ -18,8 +27,17 @@ impl StateInfo {
             castling_rights: CastlingRights::NONE,
             rule50: 0,
             game_ply: 0,
+            plies_from_null: 0,
+            key: 0u64,
+            pawn_key: 0u64,
+            checkers_bb: 0u64,
+            psq: [Score::ZERO; NrOf::SIDES],
+            phase: [0; NrOf::SIDES],
         };
     }
 }
 
 pub const CASTLING_DESTINATION_BB: Bitboard = 0x7c0000000000007c;
+
+// Piece values used by the static exchange evaluation, the king is never exchanged
+pub const SEE_VALUES: [i16; NrOf::PIECE_TYPES] = [0, 100, 300, 300, 500, 900, 0];
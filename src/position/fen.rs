@@ -10,7 +10,7 @@ impl Position {
 
         let mut square: usize = 0;
         for c in fen_parts[0].split('/').rev().collect::<Vec<&str>>().join("").chars() {
-            if c.is_digit(10) {
+            if c.is_ascii_digit() {
                 square += c.to_digit(10).unwrap() as usize;
             } else {
                 let piece_type: Piece = match c.to_ascii_lowercase() {
@@ -61,7 +61,16 @@ impl Position {
                 .position(|c| c == fen_parts[3].chars().nth(1).unwrap())
                 .unwrap();
 
-            self.states.last_mut().unwrap().en_passant_square = square_of(file, rank);
+            let en_passant_square: Square = square_of(file, rank);
+            let capturer: Piece = make_piece(self.side_to_move ^ 1, PieceType::PAWN);
+
+            // Keep the en passant square only if it can be captured, as done in Position::do_move
+            if self.bitboards.attack_bb(capturer, en_passant_square, EMPTY)
+                & self.by_type_bb[self.side_to_move][PieceType::PAWN]
+                != EMPTY
+            {
+                self.states.last_mut().unwrap().en_passant_square = en_passant_square;
+            }
         }
 
         if fen_parts.len() > 4 {
@@ -76,6 +85,22 @@ impl Position {
         for side in [Sides::WHITE, Sides::BLACK] {
             self.pinned_bb[side] = self.pinned_bb(side);
         }
+
+        let checkers_bb: Bitboard = self.compute_checkers_bb();
+        let pawn_key: u64 = self.compute_pawn_key();
+        let state: &mut StateInfo = self.states.last_mut().unwrap();
+        state.checkers_bb = checkers_bb;
+        state.pawn_key = pawn_key;
+        self.zobrist ^= self.hasher.castling(state.castling_rights) ^ self.hasher.en_passant(state.en_passant_square);
+
+        if self.side_to_move == Sides::BLACK {
+            self.zobrist ^= self.hasher.side();
+        }
+
+        state.key = self.zobrist;
+
+        #[cfg(debug_assertions)]
+        assert_eq!(self.zobrist, self.compute_key());
     }
 
     #[allow(dead_code)]
@@ -153,7 +178,7 @@ impl Position {
 
         fen.push(' ');
 
-        if self.states.last().unwrap().en_passant_square == 0 {
+        if self.states.last().unwrap().en_passant_square == NONE_SQUARE {
             fen.push('-');
         } else {
             fen += &pretty_square(self.states.last().unwrap().en_passant_square);
@@ -0,0 +1,178 @@
+use super::*;
+
+// Static Exchange Evaluation: the material outcome of the sequence of captures on the
+// destination square of a move, each side capturing with its least valuable attacker
+// and being free to stop at any time. Pinned pieces are not taken into account.
+impl Position {
+    // Whether the exchange started by the move wins at least 'threshold'
+    pub fn see(&self, mv: Move, threshold: i16) -> bool {
+        let (captured, moving, mut occupied) = match self.see_initial(mv) {
+            Some(initial) => initial,
+            None => return threshold <= 0,
+        };
+        let to: Square = mv.to_sq();
+
+        // Even winning the captured piece for free does not reach the threshold
+        let mut swap: i16 = captured - threshold;
+
+        if swap < 0 {
+            return false;
+        }
+
+        // Even losing the moving piece does not go below the threshold
+        swap = moving - swap;
+
+        if swap <= 0 {
+            return true;
+        }
+
+        let mut side: Side = self.side_to_move;
+        let mut attackers: Bitboard = self.attackers_to(to, occupied);
+        let mut result: bool = true;
+
+        loop {
+            side ^= 1;
+            attackers &= occupied;
+
+            let side_attackers: Bitboard = attackers & self.by_color_bb[side];
+
+            if side_attackers == EMPTY {
+                break;
+            }
+
+            let (piece_type, square) = self.least_valuable_attacker(side_attackers);
+            result = !result;
+
+            // The king can only capture if the other side has no attacker left
+            if piece_type == PieceType::KING {
+                return match attackers & self.by_color_bb[side ^ 1] != EMPTY {
+                    true => !result,
+                    false => result,
+                };
+            }
+
+            swap = SEE_VALUES[piece_type] - swap;
+
+            if swap < result as i16 {
+                break;
+            }
+
+            occupied ^= square_bb(square);
+            attackers |= self.xray_attackers(to, occupied);
+        }
+
+        return result;
+    }
+
+    // Exact material balance of the exchange started by the move
+    pub fn see_value(&self, mv: Move) -> i16 {
+        let (captured, moving, mut occupied) = match self.see_initial(mv) {
+            Some(initial) => initial,
+            None => return 0,
+        };
+        let to: Square = mv.to_sq();
+        let mut gain: [i16; 32] = [0; 32];
+        let mut depth: usize = 0;
+        let mut side: Side = self.side_to_move;
+        let mut attackers: Bitboard = self.attackers_to(to, occupied);
+        // Value of the piece standing on the destination square, which is the next victim
+        let mut victim: i16 = moving;
+
+        gain[0] = captured;
+
+        loop {
+            side ^= 1;
+            attackers &= occupied;
+
+            let side_attackers: Bitboard = attackers & self.by_color_bb[side];
+
+            if side_attackers == EMPTY {
+                break;
+            }
+
+            let (piece_type, square) = self.least_valuable_attacker(side_attackers);
+
+            if piece_type == PieceType::KING && attackers & self.by_color_bb[side ^ 1] != EMPTY {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = victim - gain[depth - 1];
+            victim = SEE_VALUES[piece_type];
+            occupied ^= square_bb(square);
+            attackers |= self.xray_attackers(to, occupied);
+        }
+
+        // Each side only goes on with the exchange if it is in its favor
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+
+        return gain[0];
+    }
+
+    // Value of the captured piece, value of the moving piece and occupancy once the
+    // move is made. Castling never loses material and is not evaluated.
+    fn see_initial(&self, mv: Move) -> Option<(i16, i16, Bitboard)> {
+        let from: Square = mv.from_sq();
+        let to: Square = mv.to_sq();
+        let mut occupied: Bitboard = self.by_color_bb[Sides::BOTH] ^ square_bb(from);
+        let mut captured: i16 = SEE_VALUES[type_of_piece(self.piece_on(to))];
+        let mut moving: i16 = SEE_VALUES[type_of_piece(self.piece_on(from))];
+
+        match mv.type_of() {
+            MoveTypes::CASTLING => return None,
+            MoveTypes::EN_PASSANT => {
+                captured = SEE_VALUES[PieceType::PAWN];
+                occupied ^= square_bb((to as isize - pawn_push(self.side_to_move)) as usize);
+            }
+            MoveTypes::PROMOTION => {
+                captured += SEE_VALUES[mv.promotion_type()] - SEE_VALUES[PieceType::PAWN];
+                moving = SEE_VALUES[mv.promotion_type()];
+            }
+            _ => (),
+        }
+
+        return Some((captured, moving, occupied | square_bb(to)));
+    }
+
+    // Pieces of both sides attacking the square with the given occupancy
+    pub fn attackers_to(&self, square: Square, occupied: Bitboard) -> Bitboard {
+        let bitboards = &self.bitboards;
+        let pieces =
+            |piece_type: Piece| self.by_type_bb[Sides::WHITE][piece_type] | self.by_type_bb[Sides::BLACK][piece_type];
+
+        return (bitboards.attack_bb(make_piece(Sides::BLACK, PieceType::PAWN), square, occupied)
+            & self.by_type_bb[Sides::WHITE][PieceType::PAWN])
+            | (bitboards.attack_bb(make_piece(Sides::WHITE, PieceType::PAWN), square, occupied)
+                & self.by_type_bb[Sides::BLACK][PieceType::PAWN])
+            | (bitboards.attack_bb(PieceType::KNIGHT, square, occupied) & pieces(PieceType::KNIGHT))
+            | (bitboards.attack_bb(PieceType::KING, square, occupied) & pieces(PieceType::KING))
+            | self.xray_attackers(square, occupied);
+    }
+
+    // Sliders attacking the square, revealing the ones hidden behind a piece that captured
+    fn xray_attackers(&self, square: Square, occupied: Bitboard) -> Bitboard {
+        let pieces =
+            |piece_type: Piece| self.by_type_bb[Sides::WHITE][piece_type] | self.by_type_bb[Sides::BLACK][piece_type];
+
+        return (self.bitboards.attack_bb(PieceType::BISHOP, square, occupied)
+            & (pieces(PieceType::BISHOP) | pieces(PieceType::QUEEN)))
+            | (self.bitboards.attack_bb(PieceType::ROOK, square, occupied)
+                & (pieces(PieceType::ROOK) | pieces(PieceType::QUEEN)));
+    }
+
+    fn least_valuable_attacker(&self, attackers: Bitboard) -> (Piece, Square) {
+        for piece_type in PieceType::PAWN..=PieceType::KING {
+            let bitboard: Bitboard = attackers & self.by_type_bb[Sides::WHITE][piece_type]
+                | attackers & self.by_type_bb[Sides::BLACK][piece_type];
+
+            if bitboard != EMPTY {
+                return (piece_type, bits::lsb(bitboard));
+            }
+        }
+
+        panic!("No attacker");
+    }
+}
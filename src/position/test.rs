@@ -1,19 +1,24 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
-    use std::rc::Rc;
+    use std::sync::Arc;
 
     use crate::{
         bitboards::{defs::EMPTY, Bitboards},
-        movegen::Movegen,
+        defs::{square_of, NONE_SQUARE},
+        movegen::{
+            defs::{GenTypes, Move},
+            Movegen,
+        },
         position::Position,
     };
 
     #[test]
     fn do_undo() {
-        let bitboards = Rc::new(Bitboards::new());
-        let movegen = Movegen::new(Rc::clone(&bitboards));
-        let mut initial_position = Position::new(Rc::clone(&bitboards));
-        let mut position = Position::new(Rc::clone(&bitboards));
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut initial_position = Position::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
 
         let fen: &str = "r3k2r/p1pNqpb1/bn2pnp1/3P4/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1";
         position.set(fen.to_string());
@@ -28,18 +33,245 @@ mod test {
             assert_eq!(position.by_color_bb, initial_position.by_color_bb);
             assert_eq!(position.by_type_bb, initial_position.by_type_bb);
             assert_eq!(position.pinned_bb, initial_position.pinned_bb);
+            assert_eq!(position.zobrist, initial_position.zobrist);
             assert_eq!(position.states.last().unwrap(), initial_position.states.last().unwrap());
         }
     }
 
     #[test]
     fn pinned_bb() {
-        let bitboards = Rc::new(Bitboards::new());
-        let mut position = Position::new(Rc::clone(&bitboards));
+        let bitboards = Arc::new(Bitboards::new());
+        let mut position = Position::new(Arc::clone(&bitboards));
 
         let fen: &str = "rnbqkbnr/pp1ppppp/2p5/1B6/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 2";
         position.set(fen.to_string());
 
         assert_eq!(position.pinned_bb, [EMPTY, EMPTY, EMPTY]);
     }
+
+    #[test]
+    fn zobrist_transposition() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let mut transposed_position = Position::new(Arc::clone(&bitboards));
+        let mut fen_position = Position::new(Arc::clone(&bitboards));
+
+        position.set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+        transposed_position.set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+
+        for mv_string in ["g1f3", "g8f6", "b1c3", "b8c6", "e2e4"] {
+            let mv = *movegen
+                .legal_moves(&position)
+                .iter()
+                .find(|&&mv| format!("{:?}", mv) == mv_string)
+                .unwrap();
+            position.do_move(mv);
+        }
+
+        for mv_string in ["b1c3", "b8c6", "g1f3", "g8f6", "e2e4"] {
+            let mv = *movegen
+                .legal_moves(&transposed_position)
+                .iter()
+                .find(|&&mv| format!("{:?}", mv) == mv_string)
+                .unwrap();
+            transposed_position.do_move(mv);
+        }
+
+        // The en passant square of e2e4 is not capturable, so it must not be part of the key
+        fen_position.set("r1bqkb1r/pppppppp/2n2n2/8/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq e3 0 3".to_string());
+
+        assert_eq!(position.zobrist, transposed_position.zobrist);
+        assert_eq!(position.zobrist, fen_position.zobrist);
+        assert_ne!(position.zobrist, 0);
+    }
+
+    #[test]
+    fn repetition() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        position.set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+
+        for (i, mv_string) in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"]
+            .iter()
+            .enumerate()
+        {
+            let mv = *movegen
+                .legal_moves(&position)
+                .iter()
+                .find(|&&mv| format!("{:?}", mv) == *mv_string)
+                .unwrap();
+            position.do_move(mv);
+
+            match i {
+                // The starting position is repeated once, which is only a draw inside the search tree
+                3 => assert!(!position.is_repetition(0) && position.is_repetition(5)),
+                7 => assert!(position.is_repetition(0)),
+                _ => assert!(!position.is_repetition(0)),
+            }
+        }
+    }
+
+    #[test]
+    fn see() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        // Position, move and expected material balance of the exchange
+        let suite: [(&str, &str, i16); 12] = [
+            // Undefended pawn
+            ("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5", 100),
+            // Pawn for pawn
+            ("4k3/8/2p5/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5", 0),
+            // Queen for pawn
+            ("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", "d1d5", -800),
+            // The queen behind the rook recaptures
+            ("4k3/8/2p5/3p4/8/8/3R4/3QK3 w - - 0 1", "d2d5", -300),
+            // Doubled rooks against a pawn defended by a rook
+            ("4k3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5", 100),
+            ("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5", 100),
+            // En passant, free and recaptured
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+            ("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 0),
+            // Promotions, free and recaptured by the king
+            ("7k/3P4/8/8/8/8/8/4K3 w - - 0 1", "d7d8q", 800),
+            ("4k3/3P4/8/8/8/8/8/4K3 w - - 0 1", "d7d8q", -100),
+            // The king cannot recapture a square defended through an x-ray
+            ("3rk3/8/8/8/8/8/3R4/3QK3 w - - 0 1", "d2d8", 500),
+            // Quiet move to an attacked square
+            ("4k3/8/8/3p4/8/6N1/8/4K3 w - - 0 1", "g3e4", -300),
+        ];
+
+        for (fen, mv_string, value) in suite {
+            position.set(fen.to_string());
+
+            let mv = *movegen
+                .legal_moves(&position)
+                .iter()
+                .find(|&&mv| format!("{:?}", mv) == mv_string)
+                .unwrap();
+
+            assert_eq!(position.see_value(mv), value, "{} {}", fen, mv_string);
+            assert!(position.see(mv, value), "{} {}", fen, mv_string);
+            assert!(!position.see(mv, value + 1), "{} {}", fen, mv_string);
+        }
+
+        // Both variants must agree on every move of a busy position
+        position.set("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string());
+
+        for mv in movegen.legal_moves(&position) {
+            for threshold in (-1000..=1000).step_by(50) {
+                assert_eq!(position.see(mv, threshold), position.see_value(mv) >= threshold);
+            }
+        }
+    }
+
+    #[test]
+    fn null_move() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let mut passed_position = Position::new(Arc::clone(&bitboards));
+
+        position.set("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1".to_string());
+        passed_position.set("4k3/8/8/3pP3/8/8/8/4K3 b - - 1 1".to_string());
+
+        let initial_key = position.zobrist;
+
+        // Passing loses the en passant capture and gives the turn to the opponent
+        position.do_null_move();
+        assert_eq!(position.zobrist, passed_position.zobrist);
+        assert_eq!(position.states.last().unwrap().en_passant_square, NONE_SQUARE);
+        assert!(!position.is_repetition(1));
+
+        position.undo_null_move();
+        assert_eq!(position.zobrist, initial_key);
+        assert_eq!(position.states.last().unwrap().en_passant_square, square_of(3, 5));
+    }
+
+    #[test]
+    fn gives_check() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        fn walk(position: &mut Position, movegen: &Movegen, depth: usize) {
+            for mv in movegen.legal_moves(position) {
+                let gives_check = position.gives_check(mv);
+
+                position.do_move(mv);
+                assert_eq!(gives_check, position.in_check(), "{} {:?}", position.fen(), mv);
+
+                if depth > 1 {
+                    walk(position, movegen, depth - 1);
+                }
+
+                position.undo_move(mv);
+            }
+        }
+
+        // Positions with discovered, promotion, en passant and castling checks
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "3k4/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+            "8/8/8/1k1pP2R/8/8/8/4K3 w - d6 0 1",
+        ] {
+            position.set(fen.to_string());
+            walk(&mut position, &movegen, 3);
+        }
+    }
+
+    #[test]
+    fn pseudo_legal() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        // Positions with castling, en passant, promotions, pins, single and double checks
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/8/8/3k4/3pP3/8/8/4K2R b - e3 0 1",
+            "4k3/8/8/8/1b6/8/3N4/r3K2R w K - 0 1",
+            "4k3/8/8/8/8/3n4/4r3/R3K2R w KQ - 0 1",
+        ] {
+            position.set(fen.to_string());
+
+            let legal_moves = movegen.legal_moves(&position);
+
+            for data in 0..=u16::MAX {
+                let mv = Move::new(data);
+                let legal = position.is_pseudo_legal(mv) && position.legal(mv);
+
+                assert_eq!(legal, legal_moves.contains(&mv), "{} {:?}", fen, mv);
+            }
+
+            // Pseudo-legal generation only differs by moves leaving the king in check
+            let gen_type = match position.in_check() {
+                true => GenTypes::EVASIONS,
+                false => GenTypes::NON_EVASIONS,
+            };
+
+            for mv in movegen.generate(&position, gen_type) {
+                assert!(position.is_pseudo_legal(mv), "{} {:?}", fen, mv);
+            }
+
+            // Captures and quiets split the non-evasions
+            if gen_type == GenTypes::NON_EVASIONS {
+                let mut staged = movegen.generate(&position, GenTypes::CAPTURES);
+                staged.extend(movegen.generate(&position, GenTypes::QUIETS));
+
+                assert_eq!(staged.len(), movegen.generate(&position, gen_type).len());
+                assert!(movegen
+                    .generate(&position, gen_type)
+                    .iter()
+                    .all(|mv| staged.contains(mv)));
+            }
+        }
+    }
 }
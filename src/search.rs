@@ -1,35 +1,88 @@
 pub mod defs;
+pub mod ordering;
 mod test;
 
-use core::num;
-use std::{cmp, ops::Add, time};
+use std::{
+    cmp,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 
 use crate::{
-    evaluate::{defs::PAWN_UNIT, transposition::NodeType, Eval},
-    movegen::{defs::Move, Movegen},
+    evaluate::{
+        defs::PAWN_UNIT,
+        transposition::{HashData, NodeType},
+        Eval,
+    },
+    movegen::{
+        defs::{GenTypes, Move, MoveList, MoveTypes},
+        Movegen,
+    },
     position::Position,
     time::TimeManager,
     uci::UCI,
 };
 
-use self::defs::*;
+use self::{
+    defs::*,
+    ordering::{captured_value, is_tactical, mvv_lva, History, Killers, MovePicker, OrderingStats},
+};
 
 pub struct Search {
     pub position: Position,
     pub movegen: Movegen,
     pub eval: Eval,
     pub nodes_searched: usize,
+    seldepth: usize,
+    pv: PvTable,
+    killers: Killers,
+    pub history: History,
+    pub stats: OrderingStats,
+    reductions: ReductionTable,
+    pub signals: Signals,
+    pub options: SearchOptions,
+    limits: SearchLimits,
     time: TimeManager,
+    // The main thread has id 0, it is the only one managing the time and sending output
+    id: usize,
+    helpers: Vec<Search>,
+    // Signals given to the helper threads, which stop once the main thread is done
+    helper_signals: Signals,
+    node_counter: Arc<AtomicUsize>,
+    helper_node_counters: Vec<Arc<AtomicUsize>>,
+    root_moves: Vec<RootMove>,
+    completed_depth: u8,
 }
 
 impl Search {
     pub fn new(position: Position, movegen: Movegen, eval: Eval) -> Self {
+        let signals = Signals::new();
         let mut search = Self {
             position,
             movegen,
             nodes_searched: 0,
             eval,
-            time: TimeManager::default(),
+            seldepth: 0,
+            pv: PvTable::new(),
+            killers: Killers::new(),
+            history: History::new(),
+            stats: OrderingStats::new(),
+            reductions: ReductionTable::new(),
+            time: TimeManager::default(signals.clone()),
+            signals: signals,
+            options: SearchOptions::default(),
+            limits: SearchLimits::default(),
+            id: 0,
+            helpers: Vec::new(),
+            helper_signals: Signals::new(),
+            node_counter: Arc::new(AtomicUsize::new(0)),
+            helper_node_counters: Vec::new(),
+            root_moves: Vec::new(),
+            completed_depth: 0,
         };
         search.position.set(FEN_START_POSITION.to_string());
 
@@ -37,7 +90,7 @@ impl Search {
     }
 
     pub fn run(&mut self, limits: SearchLimits) {
-        self.nodes_searched = 0;
+        self.new_search(&limits);
 
         if limits.perft > 0 {
             let nodes = self.perft(limits.perft, true);
@@ -45,62 +98,393 @@ impl Search {
             return;
         }
 
+        self.eval.transposition_table.new_search();
         self.time = TimeManager::new(
-            limits,
+            &limits,
             self.position.side_to_move,
             self.position.states.last().unwrap().game_ply,
+            self.options.move_overhead,
+            self.signals.clone(),
         );
 
         if limits.depth > 0 {
-            self.iterative_deepening(limits);
+            self.search_threads(&limits);
         } else {
             let score = self.eval.evaluate(&self.position);
             println!("info depth 0 score cp {}", score);
         }
     }
 
-    fn iterative_deepening(&mut self, limits: SearchLimits) {
-        let mut movelist = self
+    fn new_search(&mut self, limits: &SearchLimits) {
+        self.nodes_searched = 0;
+        self.node_counter.store(0, Ordering::Relaxed);
+        self.seldepth = 0;
+        self.limits = limits.clone();
+        self.stats = OrderingStats::new();
+        self.killers.clear();
+        self.history.age();
+        self.root_moves.clear();
+        self.completed_depth = 0;
+    }
+
+    // Lazy SMP: the helper threads search the same position as the main thread and only
+    // communicate through the shared transposition table, where they fill in entries
+    // the main thread benefits from. The best move is then chosen by a vote.
+    fn search_threads(&mut self, limits: &SearchLimits) {
+        self.resize_helpers();
+
+        let mut helpers = std::mem::take(&mut self.helpers);
+
+        self.helper_signals.stop.store(false, Ordering::Relaxed);
+
+        for helper in helpers.iter_mut() {
+            helper.position = self.position.clone();
+            helper.eval.share_tables(&self.eval);
+            helper.options = self.options.clone();
+            helper.new_search(limits);
+        }
+
+        thread::scope(|scope| {
+            for helper in helpers.iter_mut() {
+                thread::Builder::new()
+                    .stack_size(THREAD_STACK_SIZE)
+                    .spawn_scoped(scope, move || helper.iterative_deepening(limits))
+                    .expect("Failed to spawn a search thread");
+            }
+
+            self.iterative_deepening(limits);
+            self.helper_signals.stop.store(true, Ordering::Relaxed);
+        });
+
+        if self.root_moves.is_empty() {
+            println!("bestmove 0000");
+        } else {
+            let best_thread = self.best_thread(&helpers);
+            let best = match best_thread {
+                0 => &self.root_moves[0],
+                index => &helpers[index - 1].root_moves[0],
+            };
+
+            if best_thread > 0 {
+                self.print_info(helpers[best_thread - 1].completed_depth, 1, best);
+            }
+
+            let (best_move, line) = (best.mv, best.pv.clone());
+            self.print_bestmove(best_move, &line);
+        }
+
+        self.helpers = helpers;
+    }
+
+    // Helpers are kept between searches so that their history is not lost
+    fn resize_helpers(&mut self) {
+        let count = self.options.threads - 1;
+
+        self.helpers.truncate(count);
+        self.helper_node_counters.truncate(count);
+
+        while self.helpers.len() < count {
+            let mut helper = Search::new(self.position.clone(), self.movegen.clone(), self.eval.clone());
+            helper.id = self.helpers.len() + 1;
+            helper.signals = self.helper_signals.clone();
+            helper.time = TimeManager::default(self.helper_signals.clone());
+
+            self.helper_node_counters.push(Arc::clone(&helper.node_counter));
+            self.helpers.push(helper);
+        }
+    }
+
+    // Each thread votes for its best move, weighted by the depth it completed and by how
+    // much its score exceeds the worst one. A thread which found a faster mate is trusted.
+    // Returns 0 for the main thread and the id of a helper otherwise.
+    fn best_thread(&self, helpers: &[Search]) -> usize {
+        // Nothing was searched, either there is a single legal move or the search was stopped right away
+        if self.completed_depth == 0 {
+            return 0;
+        }
+
+        let threads: Vec<&Search> = std::iter::once(self)
+            .chain(helpers.iter())
+            .filter(|thread| thread.id == 0 || thread.completed_depth > 0)
+            .collect();
+        let min_score = threads
+            .iter()
+            .map(|thread| thread.root_moves[0].last_score())
+            .min()
+            .unwrap_or(0);
+        let mut votes: Vec<(Move, i64)> = Vec::new();
+
+        for thread in threads.iter() {
+            let root_move = &thread.root_moves[0];
+            let vote = root_move.last_score() as i64 - min_score as i64 + VOTE_BASE;
+            let weight = vote * thread.completed_depth as i64;
+
+            match votes.iter_mut().find(|(mv, _)| *mv == root_move.mv) {
+                Some((_, total)) => *total += weight,
+                None => votes.push((root_move.mv, weight)),
+            }
+        }
+
+        let votes_for = |mv: Move| {
+            votes
+                .iter()
+                .find(|(voted, _)| *voted == mv)
+                .map_or(0, |&(_, total)| total)
+        };
+        let mut best: &Search = threads[0];
+
+        for &thread in threads.iter().skip(1) {
+            let score = thread.root_moves[0].last_score();
+            let best_score = best.root_moves[0].last_score();
+
+            if (score >= VALUE_MATE_IN_MAX_PLY && score > best_score)
+                || (best_score < VALUE_MATE_IN_MAX_PLY
+                    && votes_for(thread.root_moves[0].mv) > votes_for(best.root_moves[0].mv))
+            {
+                best = thread;
+            }
+        }
+
+        return best.id;
+    }
+
+    // History is cleared for a new game, in the helper threads too
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+
+        for helper in self.helpers.iter_mut() {
+            helper.history.clear();
+        }
+    }
+
+    // Pawn structures are scored with the weights of the evaluation, the cached ones are
+    // dropped when the weights change
+    pub fn clear_pawn_tables(&mut self) {
+        self.eval.pawn_table.clear();
+
+        for helper in self.helpers.iter_mut() {
+            helper.eval.pawn_table.clear();
+        }
+    }
+
+    fn static_eval(&mut self) -> i16 {
+        return match self.options.material_from_scratch {
+            true => self.eval.evaluate_from_scratch(&self.position),
+            false => self.eval.evaluate(&self.position),
+        };
+    }
+
+    // Nodes searched by all the threads
+    pub fn total_nodes(&self) -> usize {
+        return self.nodes_searched
+            + self
+                .helper_node_counters
+                .iter()
+                .map(|counter| counter.load(Ordering::Relaxed))
+                .sum::<usize>();
+    }
+
+    fn iterative_deepening(&mut self, limits: &SearchLimits) {
+        let mut root_moves = self
             .movegen
             .legal_moves(&self.position)
             .iter()
-            .map(|&mv| (mv, 0i64))
-            .collect::<arrayvec::ArrayVec<(Move, i64), 256>>();
+            .filter(|mv| limits.search_moves.as_ref().is_none_or(|moves| moves.contains(mv)))
+            .map(|&mv| RootMove::new(mv))
+            .collect::<Vec<RootMove>>();
 
-        if movelist.len() == 0 {
-            println!("bestmove 0000");
-            return;
-        } else if movelist.len() == 1 {
-            println!("bestmove {:?}", movelist[0]);
+        if root_moves.len() <= 1 {
+            self.wait_for_stop(limits);
+            self.root_moves = root_moves;
             return;
         }
 
-        let mut last_score: i16 = 0;
+        let multi_pv = self.options.multi_pv.min(root_moves.len());
+        let mut best_move_changes: f64 = 0.0;
+
+        for depth in 1u8..=limits.depth {
+            if self.should_stop() {
+                break;
+            }
+
+            if self.id > 0 {
+                let index = (self.id - 1) % SKIP_SIZE.len();
+
+                if ((depth as usize + SKIP_PHASE[index]) / SKIP_SIZE[index]) % 2 == 1 {
+                    continue;
+                }
+            }
+
+            let previous_best_move = root_moves[0].mv;
+
+            for root_move in root_moves.iter_mut() {
+                root_move.previous_score = root_move.score;
+            }
+
+            // Each line is searched without the moves of the better lines
+            let mut lines_searched: usize = 0;
+
+            while lines_searched < multi_pv {
+                if self
+                    .aspiration_window(&mut root_moves[lines_searched..], depth)
+                    .is_none()
+                {
+                    break;
+                }
 
-        for depth in 1u8..limits.depth + 1 {
-            if self.time.should_stop() {
+                lines_searched += 1;
+            }
+
+            // The lines of an unfinished iteration mix the scores of two depths, they are
+            // neither sorted nor printed, and the iteration does not count in the vote
+            if lines_searched < multi_pv {
                 break;
             }
 
-            if let Some(best_score) = self.aspiration_window(last_score, &mut movelist, depth) {
-                last_score = best_score;
-                movelist[1..].sort_by_key(|&(_, subtree_size)| -subtree_size)
+            self.completed_depth = depth;
+
+            // A line can end up better than the previous ones because of search instability
+            root_moves[0..lines_searched].sort_by_key(|root_move| -root_move.score);
+
+            let best_score = root_moves[0].score;
+            // A helper which skipped the previous iterations has no score to compare to
+            let previous_score = root_moves[0].previous_score;
+            let score_drop = match previous_score == -VALUE_INFINITE {
+                true => 0,
+                false => (previous_score - best_score).clamp(0, SCORE_DROP_MAX),
+            };
+
+            // Older changes of the best move matter less than recent ones
+            best_move_changes /= 2.0;
+
+            if root_moves[0].mv != previous_best_move {
+                best_move_changes += 1.0;
             }
 
-            println!("info depth {} score cp {} pv {:?}", depth, last_score, movelist[0].0);
+            if self.id == 0 {
+                for (index, root_move) in root_moves[0..lines_searched].iter().enumerate() {
+                    self.print_info(depth, index + 1, root_move);
+                }
+            }
+
+            root_moves[multi_pv..].sort_by_key(|root_move| -root_move.subtree_size);
+
+            self.eval.transposition_table.store(
+                self.position.zobrist,
+                HashData {
+                    depth: depth + 1,
+                    value: best_score,
+                    best_move: root_moves[0].mv,
+                    node_type: NodeType::EXACT,
+                },
+            );
+
+            if limits.mate > 0
+                && best_score >= VALUE_MATE_IN_MAX_PLY
+                && ((VALUE_MATE - best_score + 1) / 2) as usize <= limits.mate
+            {
+                break;
+            }
+
+            // Spend more time when the best move is unstable or the score is dropping
+            let instability = 1.0 + best_move_changes;
+            let falling_eval = 1.0 + score_drop as f64 / (2 * SCORE_DROP_MAX) as f64;
+
+            if self.time.should_stop_iteration(instability * falling_eval) {
+                break;
+            }
+        }
+
+        self.wait_for_stop(limits);
+        self.node_counter.store(self.nodes_searched, Ordering::Relaxed);
+        self.root_moves = root_moves;
+    }
+
+    // The ponder move is the expected reply of the opponent, taken from the principal
+    // variation or from the transposition table if the variation is too short.
+    fn print_bestmove(&mut self, best_move: Move, line: &[Move]) {
+        let mut ponder_move = Move::none();
+
+        if line.len() >= 2 && line[0] == best_move {
+            ponder_move = line[1];
+        } else {
+            self.position.do_move(best_move);
+
+            if let Some(data) = self.eval.transposition_table.probe(self.position.zobrist) {
+                if self.position.is_pseudo_legal(data.best_move) && self.position.legal(data.best_move) {
+                    ponder_move = data.best_move;
+                }
+            }
+
+            self.position.undo_move(best_move);
+        }
+
+        match ponder_move.is_ok() {
+            true => println!("bestmove {:?} ponder {:?}", best_move, ponder_move),
+            false => println!("bestmove {:?}", best_move),
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.nodes_searched.is_multiple_of(NODE_COUNTER_INTERVAL) {
+            self.node_counter.store(self.nodes_searched, Ordering::Relaxed);
+        }
+
+        return self.nodes_searched >= self.limits.nodes || self.time.should_stop();
+    }
+
+    // The best move of an infinite or ponder search can only be sent once the GUI
+    // stops the search, even if it finished early.
+    fn wait_for_stop(&self, limits: &SearchLimits) {
+        while (limits.infinite || self.time.is_pondering()) && !self.time.is_stopped() {
+            thread::sleep(Duration::from_millis(1));
         }
+    }
 
-        println!("bestmove {:?}", movelist[0].0);
+    fn print_info(&self, depth: u8, multi_pv: usize, root_move: &RootMove) {
+        let elapsed = self.time.elapsed();
+        let nodes = self.total_nodes();
+
+        println!(
+            "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+            depth,
+            self.seldepth,
+            multi_pv,
+            UCI::format_score(root_move.last_score()),
+            nodes,
+            nodes as u128 * 1000 / (elapsed + 1),
+            elapsed,
+            self.eval.transposition_table.hashfull(),
+            root_move
+                .pv
+                .iter()
+                .map(|mv| format!("{:?}", mv))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
     }
 
-    fn aspiration_window(&mut self, last_score: i16, moves: &mut [(Move, i64)], depth: u8) -> Option<i16> {
+    // Searches the root moves with a window around the score of the first one in the
+    // previous iteration, widening it until the score falls inside. The best move ends
+    // up first.
+    fn aspiration_window(&mut self, moves: &mut [RootMove], depth: u8) -> Option<i16> {
         let mut delta = PAWN_UNIT / 2;
-        let mut alpha = cmp::max(last_score - delta, -VALUE_MATE);
-        let mut beta = cmp::min(last_score + delta, VALUE_MATE);
+        let last_score = moves[0].previous_score;
+        let (mut alpha, mut beta) = match last_score == -VALUE_INFINITE {
+            true => (-VALUE_MATE, VALUE_MATE),
+            false => (
+                cmp::max(last_score - delta, -VALUE_MATE),
+                cmp::min(last_score + delta, VALUE_MATE),
+            ),
+        };
 
         loop {
-            let (score, index) = self.search_root(moves, alpha, beta, depth)?;
-            (&mut moves[0..index + 1]).rotate_right(1);
+            let score = self.search_root(moves, alpha, beta, depth);
+
+            // Moves which did not raise alpha keep their order
+            moves.sort_by_key(|root_move| -root_move.score);
+
+            let score = score?;
 
             delta += delta / 3;
 
@@ -114,44 +498,46 @@ impl Search {
         }
     }
 
-    fn search_root(&mut self, moves: &mut [(Move, i64)], alpha: i16, beta: i16, depth: u8) -> Option<(i16, usize)> {
+    // Moves which do not raise alpha get a score of -VALUE_INFINITE, as only an upper bound is known
+    fn search_root(&mut self, moves: &mut [RootMove], alpha: i16, beta: i16, depth: u8) -> Option<i16> {
         let mut alpha = alpha;
         let mut best_score = -VALUE_INFINITE;
-        let mut best_move_index = 0;
-        let mut increased_alpha = false;
 
-        for (i, &mut (mv, ref mut subtree_size)) in moves.iter_mut().enumerate() {
+        for root_move in moves.iter_mut() {
+            root_move.score = -VALUE_INFINITE;
+        }
+
+        for (i, root_move) in moves.iter_mut().enumerate() {
+            let mv = root_move.mv;
+            let nodes_before = self.nodes_searched;
+            self.pv.clear(0);
             self.position.do_move(mv);
             let mut score = Some(VALUE_INFINITE);
 
             if i > 0 {
-                score = self.search(-alpha - 1, -alpha, depth).map(|v| -v);
+                score = self.search(-alpha - 1, -alpha, depth, 1).map(|v| -v);
             }
 
             if Some(alpha) < score {
-                score = self.search(-beta, -alpha, depth).map(|v| -v);
+                score = self.search(-beta, -alpha, depth, 1).map(|v| -v);
             }
 
             self.position.undo_move(mv);
+            root_move.subtree_size = (self.nodes_searched - nodes_before) as i64;
 
             match score {
-                None => {
-                    if increased_alpha {
-                        return Some((best_score, best_move_index));
-                    } else {
-                        return None;
-                    }
-                }
+                // The iteration is incomplete even if alpha was raised, its score is not stored
+                None => return None,
                 Some(value) => {
                     if value > best_score {
                         best_score = value;
-                        best_move_index = i;
                     }
 
                     if value > alpha {
                         alpha = value;
-                        increased_alpha = true;
-                        // self.add_pv_move(mov, 0);
+                        self.pv.update(0, mv);
+                        root_move.score = value;
+                        root_move.pv = self.pv.line().to_vec();
                     }
 
                     if value >= beta {
@@ -161,39 +547,160 @@ impl Search {
             }
         }
 
-        return Some((best_score, best_move_index));
+        return Some(best_score);
     }
 
-    fn search(&mut self, alpha: i16, beta: i16, depth: u8) -> Option<i16> {
-        if self.time.should_stop() {
+    fn search(&mut self, alpha: i16, beta: i16, depth: u8, ply: usize) -> Option<i16> {
+        self.pv.clear(ply);
+
+        if self.should_stop() {
             return None;
         }
 
-        if depth == 0 {
-            return Some(self.eval.evaluate(&self.position));
+        self.nodes_searched += 1;
+        self.seldepth = self.seldepth.max(ply);
+
+        let in_check = self.position.in_check();
+
+        if self.position.is_repetition(ply) {
+            return Some(VALUE_DRAW);
+        }
+
+        // A checkmate takes precedence over the fifty-move rule
+        if self.position.states.last().unwrap().rule50 >= 100 {
+            return match in_check && self.movegen.legal_moves(&self.position).is_empty() {
+                true => Some(mated_in(ply)),
+                false => Some(VALUE_DRAW),
+            };
+        }
+
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(alpha, beta, ply);
         }
 
         let is_pv = alpha + 1 != beta;
+        let key = self.position.zobrist;
+        let mut hash_move = Move::none();
+
+        if let Some(data) = self.eval.transposition_table.probe(key) {
+            hash_move = data.best_move;
+
+            // PV nodes are always searched to get a complete and exact principal variation
+            if !is_pv && data.depth >= depth {
+                let value = value_from_tt(data.value, ply);
+
+                match data.node_type {
+                    NodeType::EXACT => return Some(value),
+                    NodeType::LOWERBOUND if value >= beta => return Some(value),
+                    NodeType::UPPERBOUND if value <= alpha => return Some(value),
+                    _ => (),
+                }
+            }
+        }
+
+        let static_eval = match in_check {
+            true => -VALUE_INFINITE,
+            false => self.static_eval(),
+        };
 
-        // TODO: Add check for Draw  and 50 move rule ?
+        if !is_pv && !in_check {
+            // Reverse futility pruning: the position is good enough for a shallow search to fail high
+            if self.options.futility
+                && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && static_eval < VALUE_MATE_IN_MAX_PLY
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth as i16 >= beta
+            {
+                return Some(static_eval);
+            }
+
+            // Null move pruning: if passing still fails high, a real move would most likely too.
+            // Never done twice in a row, nor with only pawns left as zugzwang is then common.
+            if self.options.null_move
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && static_eval >= beta
+                && self.position.states.last().unwrap().plies_from_null > 0
+                && self.position.has_non_pawn_material(self.position.side_to_move)
+            {
+                let reduction = 3 + depth / 4;
+
+                self.position.do_null_move();
+                let score = self
+                    .search(-beta, -beta + 1, depth.saturating_sub(reduction + 1), ply + 1)
+                    .map(|v| -v);
+                self.position.undo_null_move();
+
+                let value = score?;
+
+                // Mate scores found after passing are not proven
+                if value >= beta {
+                    return match value >= VALUE_MATE_IN_MAX_PLY {
+                        true => Some(beta),
+                        false => Some(value),
+                    };
+                }
+            }
+        }
+
+        // Futility pruning: quiet moves are unlikely to raise alpha when the static evaluation is far below it
+        let futility_pruning = self.options.futility
+            && !is_pv
+            && !in_check
+            && depth <= FUTILITY_MAX_DEPTH
+            && static_eval + FUTILITY_MARGINS[depth as usize] <= alpha;
 
-        let movelist = self.movegen.legal_moves(&self.position);
         let mut alpha = alpha;
-        let mut best_score = -VALUE_MATE;
-        let mut num_moves_searched = 0;
+        let mut best_score = -VALUE_INFINITE;
+        let mut best_move = Move::none();
+        let mut node_type = NodeType::UPPERBOUND;
+        let mut picker = MovePicker::new(&self.position, hash_move, self.killers.get(ply));
+        let mut quiets_searched = MoveList::new();
+        let mut num_moves_searched: usize = 0;
+
+        while let Some(mv) = picker.next(&self.position, &self.movegen, &self.history) {
+            let is_quiet = !is_tactical(&self.position, mv);
+            let gives_check = self.position.gives_check(mv);
+
+            if futility_pruning && is_quiet && !gives_check && num_moves_searched > 0 {
+                continue;
+            }
+
+            // Check extension: checks which do not lose material are searched one ply deeper
+            let new_depth = match gives_check && self.position.see(mv, 0) {
+                true => depth,
+                false => depth - 1,
+            };
 
-        for mv in movelist {
             self.position.do_move(mv);
             let mut score: Option<i16> = Some(VALUE_MATE);
 
-            if !(is_pv && num_moves_searched == 0) {
-                score = self.search(-alpha - 1, -alpha, depth - 1).map(|v| -v);
+            // Late move reductions: moves ordered late are searched at a reduced depth
+            // first, and only searched again at full depth if they raise alpha.
+            if self.options.late_move_reductions
+                && depth >= LMR_MIN_DEPTH
+                && num_moves_searched >= LMR_MIN_MOVES
+                && is_quiet
+                && !in_check
+                && !gives_check
+            {
+                let reduction = match is_pv {
+                    true => self.reductions.get(depth, num_moves_searched).saturating_sub(1),
+                    false => self.reductions.get(depth, num_moves_searched),
+                }
+                .min(depth - 2);
+
+                if reduction > 0 {
+                    score = self
+                        .search(-alpha - 1, -alpha, new_depth - reduction, ply + 1)
+                        .map(|v| -v);
+                }
             }
 
-            num_moves_searched += 1;
+            if !(is_pv && num_moves_searched == 0) && Some(alpha) < score {
+                score = self.search(-alpha - 1, -alpha, new_depth, ply + 1).map(|v| -v);
+            }
 
             if Some(alpha) < score && is_pv {
-                score = self.search(-beta, -alpha, depth - 1).map(|v| -v);
+                score = self.search(-beta, -alpha, new_depth, ply + 1).map(|v| -v);
             }
 
             self.position.undo_move(mv);
@@ -205,17 +712,137 @@ impl Search {
                 Some(value) => {
                     if value > best_score {
                         best_score = value;
+                        best_move = mv;
                     }
 
                     if value > alpha {
                         alpha = value;
+                        node_type = NodeType::EXACT;
+
+                        if is_pv {
+                            self.pv.update(ply, mv);
+                        }
                     }
 
                     if value >= beta {
+                        node_type = NodeType::LOWERBOUND;
+                        self.stats.update(num_moves_searched);
+
+                        if is_quiet {
+                            self.killers.update(ply, mv);
+                            self.history
+                                .update(self.position.side_to_move, mv, &quiets_searched, depth);
+                        }
+
                         break;
                     }
                 }
             }
+
+            if is_quiet {
+                quiets_searched.push(mv);
+            }
+
+            num_moves_searched += 1;
+        }
+
+        // Futility pruning always searches the first move, so no score means no legal move
+        if best_score == -VALUE_INFINITE {
+            return match in_check {
+                true => Some(mated_in(ply)),
+                false => Some(VALUE_DRAW),
+            };
+        }
+
+        self.eval.transposition_table.store(
+            key,
+            HashData {
+                depth: depth,
+                value: value_to_tt(best_score, ply),
+                best_move: best_move,
+                node_type: node_type,
+            },
+        );
+
+        return Some(best_score);
+    }
+
+    // Only search captures and promotions until the position is quiet, so that the
+    // static evaluation is not called in the middle of an exchange.
+    fn quiescence(&mut self, alpha: i16, beta: i16, ply: usize) -> Option<i16> {
+        self.pv.clear(ply);
+
+        if self.should_stop() {
+            return None;
+        }
+
+        self.nodes_searched += 1;
+        self.seldepth = self.seldepth.max(ply);
+
+        let in_check = self.position.in_check();
+        let mut alpha = alpha;
+        // Only evasions are generated when in check, so having no move means being checkmated
+        let mut best_score = mated_in(ply);
+        let mut stand_pat = -VALUE_INFINITE;
+
+        if ply >= MAX_PLY {
+            return Some(self.static_eval());
+        }
+
+        // When in check every evasion is searched as standing pat is not an option
+        let gen_type = match in_check {
+            true => GenTypes::EVASIONS,
+            false => {
+                stand_pat = self.static_eval();
+
+                if stand_pat >= beta {
+                    return Some(stand_pat);
+                }
+
+                if stand_pat > alpha {
+                    alpha = stand_pat;
+                }
+
+                best_score = stand_pat;
+                GenTypes::CAPTURES
+            }
+        };
+
+        let mut movelist = self.movegen.legal_moves_of_type(&self.position, gen_type);
+        movelist.sort_by_key(|&mv| -mvv_lva(&self.position, mv));
+
+        for mv in movelist {
+            // Delta pruning: skip captures that cannot raise alpha even with a safety margin.
+            // The sum can exceed an i16 when the evaluation is close to its maximum.
+            if !in_check
+                && mv.type_of() != MoveTypes::PROMOTION
+                && stand_pat as i32 + captured_value(&self.position, mv) as i32 + DELTA_MARGIN as i32 <= alpha as i32
+            {
+                continue;
+            }
+
+            // Captures losing material are unlikely to be good unless they are evasions
+            if !in_check && !self.position.see(mv, 0) {
+                continue;
+            }
+
+            self.position.do_move(mv);
+            let score = self.quiescence(-beta, -alpha, ply + 1).map(|v| -v);
+            self.position.undo_move(mv);
+
+            let value = score?;
+
+            if value > best_score {
+                best_score = value;
+            }
+
+            if value > alpha {
+                alpha = value;
+            }
+
+            if value >= beta {
+                break;
+            }
         }
 
         return Some(best_score);
@@ -225,7 +852,7 @@ impl Search {
         let mut count: u128;
         let mut nodes: u128 = 0;
         let leaf: bool = depth == 2;
-        let moves: Vec<Move> = self.movegen.legal_moves(&self.position);
+        let moves: MoveList = self.movegen.legal_moves(&self.position);
 
         for mv in moves.iter() {
             if depth <= 1 {
@@ -249,3 +876,30 @@ impl Search {
         return nodes;
     }
 }
+
+// Scores of mates are relative to the root, so that shorter mates are preferred
+fn mated_in(ply: usize) -> i16 {
+    return -VALUE_MATE + ply as i16;
+}
+
+// Mate scores are stored relative to the current node rather than the root,
+// so that they remain valid when the position is reached at a different ply.
+fn value_to_tt(value: i16, ply: usize) -> i16 {
+    if value >= VALUE_MATE_IN_MAX_PLY {
+        return value + ply as i16;
+    } else if value <= -VALUE_MATE_IN_MAX_PLY {
+        return value - ply as i16;
+    }
+
+    return value;
+}
+
+fn value_from_tt(value: i16, ply: usize) -> i16 {
+    if value >= VALUE_MATE_IN_MAX_PLY {
+        return value - ply as i16;
+    } else if value <= -VALUE_MATE_IN_MAX_PLY {
+        return value + ply as i16;
+    }
+
+    return value;
+}
@@ -1,8 +1,44 @@
-use crate::defs::{Side, Sides};
+use std::sync::{atomic::AtomicBool, Arc};
+
+use crate::{
+    defs::{Side, Sides},
+    evaluate::{defs::PAWN_UNIT, nnue::CLASSICAL_EVALUATION},
+    movegen::defs::Move,
+};
+
+pub const MAX_PLY: usize = 246;
+
+pub const DELTA_MARGIN: i16 = 2 * PAWN_UNIT;
+
+// Null move pruning is only tried with enough depth left to save work
+pub const NULL_MOVE_MIN_DEPTH: u8 = 3;
+// Reverse futility pruning prunes nodes whose static evaluation exceeds beta by this margin per depth
+pub const REVERSE_FUTILITY_MAX_DEPTH: u8 = 6;
+pub const REVERSE_FUTILITY_MARGIN: i16 = PAWN_UNIT;
+// Futility pruning skips quiet moves that cannot raise the static evaluation above alpha
+pub const FUTILITY_MAX_DEPTH: u8 = 3;
+pub const FUTILITY_MARGINS: [i16; FUTILITY_MAX_DEPTH as usize + 1] = [0, 2 * PAWN_UNIT, 3 * PAWN_UNIT, 5 * PAWN_UNIT];
+// Late move reductions only apply to quiet moves searched after the first ones
+pub const LMR_MIN_DEPTH: u8 = 3;
+pub const LMR_MIN_MOVES: usize = 3;
+
+// Largest score drop between two iterations taken into account when allocating more time
+pub const SCORE_DROP_MAX: i16 = PAWN_UNIT;
+
+// Helper threads skip some iterations so that they do not all search the same tree.
+// A helper skips a depth when ((depth + phase) / size) is odd.
+pub const SKIP_SIZE: [usize; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+pub const SKIP_PHASE: [usize; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+// Helper threads make their node count visible to the main thread at this interval
+pub const NODE_COUNTER_INTERVAL: usize = 1024;
+// The search recurses deeply, the default stack of a spawned thread is too small
+pub const THREAD_STACK_SIZE: usize = 32 * 1024 * 1024;
+// Base weight of a thread's vote for its best move, on top of its score above the worst thread
+pub const VOTE_BASE: i64 = 14;
 
 pub const FEN_START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct SearchLimits {
     pub perft: u8,
     pub depth: u8,
@@ -15,6 +51,9 @@ pub struct SearchLimits {
     pub nodes: usize,
     pub mate: usize,
     pub movetime: usize,
+    pub infinite: bool,
+    // Root moves the search is restricted to, all of them when searchmoves is not given
+    pub search_moves: Option<Vec<Move>>,
 }
 
 impl SearchLimits {
@@ -31,9 +70,19 @@ impl SearchLimits {
             nodes: usize::MAX,
             mate: 0,
             movetime: usize::MAX,
+            infinite: false,
+            search_moves: None,
         }
     }
 
+    pub fn increment(&self, side: Side) -> usize {
+        return match side {
+            Sides::WHITE => self.white_inc,
+            Sides::BLACK => self.black_inc,
+            _ => panic!("Invalid side"),
+        };
+    }
+
     pub fn time(&self, side: Side) -> u64 {
         return match side {
             Sides::WHITE => self.white_time,
@@ -43,8 +92,154 @@ impl SearchLimits {
     }
 }
 
+// Settings changed through UCI options, kept across searches
+#[derive(Clone)]
+pub struct SearchOptions {
+    // Milliseconds removed from every time budget to absorb the GUI latency
+    pub move_overhead: u64,
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub futility: bool,
+    // Number of best root moves searched with an exact score
+    pub multi_pv: usize,
+    // Number of threads searching the same position
+    pub threads: usize,
+    // Network evaluation, and the file it is read from
+    pub eval_file: String,
+    // Only set by bench, to compare with the material computed at every evaluation
+    pub material_from_scratch: bool,
+}
+
+impl SearchOptions {
+    pub fn default() -> Self {
+        return Self {
+            move_overhead: 10,
+            null_move: true,
+            late_move_reductions: true,
+            futility: true,
+            multi_pv: 1,
+            threads: 1,
+            eval_file: CLASSICAL_EVALUATION.to_string(),
+            material_from_scratch: false,
+        };
+    }
+}
+
+// Score and principal variation of a root move in the last iteration
+pub struct RootMove {
+    pub mv: Move,
+    pub score: i16,
+    pub previous_score: i16,
+    // Number of nodes searched below the move, used to order the moves without an exact score
+    pub subtree_size: i64,
+    pub pv: Vec<Move>,
+}
+
+impl RootMove {
+    pub fn new(mv: Move) -> Self {
+        return Self {
+            mv: mv,
+            score: -VALUE_INFINITE,
+            previous_score: -VALUE_INFINITE,
+            subtree_size: 0,
+            pv: vec![mv],
+        };
+    }
+
+    // Score of the last iteration which searched the move, in case the current one was interrupted
+    pub fn last_score(&self) -> i16 {
+        return match self.score == -VALUE_INFINITE {
+            true => self.previous_score,
+            false => self.score,
+        };
+    }
+}
+
+// Flags shared with the UCI input thread to control a running search
+#[derive(Clone)]
+pub struct Signals {
+    pub stop: Arc<AtomicBool>,
+    pub ponder: Arc<AtomicBool>,
+    // Set from the moment a go command is read until its search returns, stop and quit only apply then
+    pub searching: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn new() -> Self {
+        return Self {
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+            searching: Arc::new(AtomicBool::new(false)),
+        };
+    }
+}
+
 pub const VALUE_ZERO: i16 = 0;
 pub const VALUE_DRAW: i16 = VALUE_ZERO;
 pub const VALUE_MATE: i16 = 32000;
+pub const VALUE_MATE_IN_MAX_PLY: i16 = VALUE_MATE - MAX_PLY as i16;
 pub const VALUE_INFINITE: i16 = 32001;
+// Static evaluations stay below the mate scores
+pub const VALUE_EVAL_MAX: i16 = VALUE_MATE_IN_MAX_PLY - 1;
+#[allow(dead_code)]
 pub const VALUE_NONE: i16 = 32002;
+
+// Triangular table holding the principal variation of each ply. The line found at
+// a given ply is made of the best move followed by the line of the next ply.
+pub struct PvTable {
+    moves: Vec<[Move; MAX_PLY + 1]>,
+    length: [usize; MAX_PLY + 1],
+}
+
+impl PvTable {
+    pub fn new() -> Self {
+        return Self {
+            moves: vec![[Move::none(); MAX_PLY + 1]; MAX_PLY + 1],
+            length: [0; MAX_PLY + 1],
+        };
+    }
+
+    // Should be called when entering a node, before any early return
+    pub fn clear(&mut self, ply: usize) {
+        self.length[ply] = ply;
+    }
+
+    pub fn update(&mut self, ply: usize, mv: Move) {
+        let length = self.length[ply + 1].max(ply + 1);
+
+        self.moves[ply][ply] = mv;
+
+        for i in ply + 1..length {
+            self.moves[ply][i] = self.moves[ply + 1][i];
+        }
+
+        self.length[ply] = length;
+    }
+
+    pub fn line(&self) -> &[Move] {
+        return &self.moves[0][0..self.length[0]];
+    }
+}
+
+// Late move reductions, growing logarithmically with the depth and the number of moves searched
+pub struct ReductionTable {
+    reductions: [[u8; 64]; 64],
+}
+
+impl ReductionTable {
+    pub fn new() -> Self {
+        let mut reductions = [[0u8; 64]; 64];
+
+        for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+                *reduction = (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as u8;
+            }
+        }
+
+        return Self { reductions: reductions };
+    }
+
+    pub fn get(&self, depth: u8, move_number: usize) -> u8 {
+        return self.reductions[(depth as usize).min(63)][move_number.min(63)];
+    }
+}
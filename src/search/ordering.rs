@@ -0,0 +1,294 @@
+use arrayvec::ArrayVec;
+
+use crate::{
+    defs::*,
+    evaluate::tables::PIECE_VALUES_INITIAL,
+    movegen::{
+        defs::{GenTypes, Move, MoveTypes, MAX_MOVES},
+        Movegen,
+    },
+    position::Position,
+};
+
+use super::defs::MAX_PLY;
+
+// History scores are kept in this range so that recent cutoffs can outweigh old ones
+const MAX_HISTORY: i32 = 16384;
+
+pub type Stage = usize;
+pub struct Stages {}
+
+impl Stages {
+    pub const HASH_MOVE: Stage = 0;
+    pub const CAPTURES_INIT: Stage = 1;
+    pub const CAPTURES: Stage = 2;
+    pub const KILLERS: Stage = 3;
+    pub const QUIETS_INIT: Stage = 4;
+    pub const QUIETS: Stage = 5;
+    pub const BAD_CAPTURES: Stage = 6;
+    pub const EVASIONS_INIT: Stage = 7;
+    pub const EVASIONS: Stage = 8;
+    pub const DONE: Stage = 9;
+}
+
+// Evasions capturing the checking piece are tried before the other ones
+const EVASION_CAPTURE_BONUS: i32 = 1 << 20;
+
+// Returns the legal moves of a node one at a time, in the order they are likely to
+// produce a cutoff. Each stage only generates the moves it needs, so that a cutoff
+// on the hash move or a capture saves generating the quiet moves. The best remaining
+// move is selected each time so that the list is never fully sorted. Captures losing
+// material are searched last.
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Move,
+    killers: [Move; 2],
+    killer_index: usize,
+    scored: ArrayVec<(Move, i32), MAX_MOVES>,
+    bad_captures: ArrayVec<(Move, i32), MAX_MOVES>,
+}
+
+impl MovePicker {
+    pub fn new(position: &Position, hash_move: Move, killers: [Move; 2]) -> Self {
+        return Self {
+            stage: Stages::HASH_MOVE,
+            hash_move: match position.is_pseudo_legal(hash_move) && position.legal(hash_move) {
+                true => hash_move,
+                false => Move::none(),
+            },
+            killers: killers,
+            killer_index: 0,
+            scored: ArrayVec::new(),
+            bad_captures: ArrayVec::new(),
+        };
+    }
+
+    pub fn next(&mut self, position: &Position, movegen: &Movegen, history: &History) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stages::HASH_MOVE => {
+                    self.stage = match position.in_check() {
+                        true => Stages::EVASIONS_INIT,
+                        false => Stages::CAPTURES_INIT,
+                    };
+
+                    if self.hash_move.is_ok() {
+                        return Some(self.hash_move);
+                    }
+                }
+                Stages::CAPTURES_INIT => {
+                    self.scored = movegen
+                        .generate(position, GenTypes::CAPTURES)
+                        .iter()
+                        .filter(|&&mv| mv != self.hash_move)
+                        .map(|&mv| (mv, mvv_lva(position, mv) as i32))
+                        .collect();
+                    self.stage = Stages::CAPTURES;
+                }
+                Stages::CAPTURES => match self.select_best() {
+                    Some(mv) if !position.legal(mv) => (),
+                    Some(mv) if !position.see(mv, 0) => self.bad_captures.push((mv, position.see_value(mv) as i32)),
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stages::KILLERS,
+                },
+                Stages::KILLERS => {
+                    if self.killer_index == self.killers.len() {
+                        self.stage = Stages::QUIETS_INIT;
+                        continue;
+                    }
+
+                    let killer = self.killers[self.killer_index];
+                    self.killer_index += 1;
+
+                    // Killers come from sibling nodes and may not be legal, or quiet, here
+                    if killer != self.hash_move
+                        && position.is_pseudo_legal(killer)
+                        && !is_tactical(position, killer)
+                        && position.legal(killer)
+                    {
+                        return Some(killer);
+                    }
+                }
+                Stages::QUIETS_INIT => {
+                    let us = position.side_to_move;
+
+                    self.scored = movegen
+                        .generate(position, GenTypes::QUIETS)
+                        .iter()
+                        .filter(|&&mv| mv != self.hash_move && !self.killers.contains(&mv))
+                        .map(|&mv| (mv, history.get(us, mv)))
+                        .collect();
+                    self.stage = Stages::QUIETS;
+                }
+                Stages::QUIETS => match self.select_best() {
+                    Some(mv) if !position.legal(mv) => (),
+                    Some(mv) => return Some(mv),
+                    None => {
+                        // The captures losing the least material come first
+                        self.scored = std::mem::take(&mut self.bad_captures);
+                        self.stage = Stages::BAD_CAPTURES;
+                    }
+                },
+                Stages::BAD_CAPTURES => match self.select_best() {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stages::DONE,
+                },
+                Stages::EVASIONS_INIT => {
+                    let us = position.side_to_move;
+
+                    self.scored = movegen
+                        .generate(position, GenTypes::EVASIONS)
+                        .iter()
+                        .filter(|&&mv| mv != self.hash_move)
+                        .map(|&mv| match is_tactical(position, mv) {
+                            true => (mv, EVASION_CAPTURE_BONUS + mvv_lva(position, mv) as i32),
+                            false => (mv, history.get(us, mv)),
+                        })
+                        .collect();
+                    self.stage = Stages::EVASIONS;
+                }
+                Stages::EVASIONS => match self.select_best() {
+                    Some(mv) if !position.legal(mv) => (),
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stages::DONE,
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    fn select_best(&mut self) -> Option<Move> {
+        let index = (0..self.scored.len()).max_by_key(|&i| self.scored[i].1)?;
+
+        return Some(self.scored.swap_remove(index).0);
+    }
+}
+
+// Quiet moves that caused a cutoff at the same ply in another branch of the tree
+pub struct Killers {
+    moves: [[Move; 2]; MAX_PLY + 1],
+}
+
+impl Killers {
+    pub fn new() -> Self {
+        return Self {
+            moves: [[Move::none(); 2]; MAX_PLY + 1],
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.moves = [[Move::none(); 2]; MAX_PLY + 1];
+    }
+
+    pub fn get(&self, ply: usize) -> [Move; 2] {
+        return self.moves[ply];
+    }
+
+    pub fn update(&mut self, ply: usize, mv: Move) {
+        if self.moves[ply][0] != mv {
+            self.moves[ply][1] = self.moves[ply][0];
+            self.moves[ply][0] = mv;
+        }
+    }
+}
+
+// Butterfly history: how often a quiet move, identified by its squares, caused a
+// cutoff anywhere in the tree. Deeper cutoffs are more reliable and weigh more.
+pub struct History {
+    scores: Vec<[[i32; NrOf::SQUARES]; NrOf::SQUARES]>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        return Self {
+            scores: vec![[[0; NrOf::SQUARES]; NrOf::SQUARES]; 2],
+        };
+    }
+
+    // Scores are kept between searches as they remain mostly relevant
+    pub fn age(&mut self) {
+        for table in self.scores.iter_mut() {
+            for from in table.iter_mut() {
+                for score in from.iter_mut() {
+                    *score /= 2;
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.scores = vec![[[0; NrOf::SQUARES]; NrOf::SQUARES]; 2];
+    }
+
+    pub fn get(&self, side: Side, mv: Move) -> i32 {
+        return self.scores[side][mv.from_sq()][mv.to_sq()];
+    }
+
+    // The move causing the cutoff gets a bonus, while the quiet moves searched before it get a malus
+    pub fn update(&mut self, side: Side, mv: Move, tried: &[Move], depth: u8) {
+        let bonus = (depth as i32 * depth as i32).min(MAX_HISTORY);
+
+        self.add(side, mv, bonus);
+
+        for &other in tried.iter().filter(|&&other| other != mv) {
+            self.add(side, other, -bonus);
+        }
+    }
+
+    fn add(&mut self, side: Side, mv: Move, bonus: i32) {
+        let score = &mut self.scores[side][mv.from_sq()][mv.to_sq()];
+
+        // Scale the bonus down as the score gets closer to the limit
+        *score += bonus - *score * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+// Number of beta cutoffs, and how many of them were produced by the first move searched
+#[derive(Clone, Copy)]
+pub struct OrderingStats {
+    pub cutoffs: usize,
+    pub first_move_cutoffs: usize,
+}
+
+impl OrderingStats {
+    pub fn new() -> Self {
+        return Self {
+            cutoffs: 0,
+            first_move_cutoffs: 0,
+        };
+    }
+
+    pub fn update(&mut self, move_number: usize) {
+        self.cutoffs += 1;
+
+        if move_number == 0 {
+            self.first_move_cutoffs += 1;
+        }
+    }
+
+    // Percentage of cutoffs produced by the first move
+    pub fn first_move_rate(&self) -> f64 {
+        return self.first_move_cutoffs as f64 * 100.0 / self.cutoffs.max(1) as f64;
+    }
+}
+
+// Captures and promotions change the material balance and are ordered separately
+pub fn is_tactical(position: &Position, mv: Move) -> bool {
+    return mv.type_of() == MoveTypes::PROMOTION || position.is_capture(mv);
+}
+
+pub fn captured_value(position: &Position, mv: Move) -> i16 {
+    return match mv.type_of() {
+        MoveTypes::EN_PASSANT => PIECE_VALUES_INITIAL[PieceType::PAWN],
+        MoveTypes::CASTLING => 0,
+        _ => PIECE_VALUES_INITIAL[type_of_piece(position.piece_on(mv.to_sq()))],
+    };
+}
+
+// Most Valuable Victim - Least Valuable Aggressor
+pub fn mvv_lva(position: &Position, mv: Move) -> i16 {
+    let attacker: Piece = type_of_piece(position.piece_on(mv.from_sq()));
+
+    return 10 * (captured_value(position, mv) + PIECE_VALUES_INITIAL[mv.promotion_type()])
+        - PIECE_VALUES_INITIAL[attacker] / 100;
+}
@@ -1,20 +1,33 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
-    use std::rc::Rc;
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        thread,
+        time::{Duration, Instant},
+    };
 
     use crate::{
         bitboards::Bitboards,
-        movegen::Movegen,
+        evaluate::Eval,
+        movegen::{defs::Move, Movegen},
         position::Position,
-        search::{defs::FEN_START_POSITION, Search}, evaluate::Eval,
+        search::{
+            defs::{SearchLimits, FEN_START_POSITION, VALUE_DRAW, VALUE_INFINITE, VALUE_MATE, VALUE_MATE_IN_MAX_PLY},
+            ordering::{History, MovePicker},
+            Search,
+        },
     };
 
+    // Ra6, whatever black plays, then b7 or Rxa7
+    const MATE_IN_TWO: &str = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+
     #[test]
     fn perft_startpos() {
-        let bitboards = Rc::new(Bitboards::new());
-        let movegen = Movegen::new(Rc::clone(&bitboards));
-        let position = Position::new(Rc::clone(&bitboards));
-        let mut search = Search::new(position, movegen, Eval::new());
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
 
         search.position.set(FEN_START_POSITION.to_string());
 
@@ -27,10 +40,10 @@ mod test {
 
     #[test]
     fn perft_kiwipete() {
-        let bitboards = Rc::new(Bitboards::new());
-        let movegen = Movegen::new(Rc::clone(&bitboards));
-        let position = Position::new(Rc::clone(&bitboards));
-        let mut search = Search::new(position, movegen, Eval::new());
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
 
         search
             .position
@@ -45,10 +58,10 @@ mod test {
 
     #[test]
     fn perft_edwards() {
-        let bitboards = Rc::new(Bitboards::new());
-        let movegen = Movegen::new(Rc::clone(&bitboards));
-        let position = Position::new(Rc::clone(&bitboards));
-        let mut search = Search::new(position, movegen, Eval::new());
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
 
         search
             .position
@@ -63,14 +76,12 @@ mod test {
 
     #[test]
     fn perft_endgame() {
-        let bitboards = Rc::new(Bitboards::new());
-        let movegen = Movegen::new(Rc::clone(&bitboards));
-        let position = Position::new(Rc::clone(&bitboards));
-        let mut search = Search::new(position, movegen, Eval::new());
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
 
-        search
-            .position
-            .set("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -".to_string());
+        search.position.set("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -".to_string());
 
         assert_eq!(search.perft(1, true), 14);
         assert_eq!(search.perft(2, true), 191);
@@ -79,12 +90,316 @@ mod test {
         assert_eq!(search.perft(5, true), 674624);
     }
 
+    #[test]
+    fn quiescence_hanging_queen() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+
+        search.position.set("4k3/8/8/2p5/3Q4/8/8/4K3 b - - 0 1".to_string());
+
+        assert!(search.eval.evaluate(&search.position) < -500);
+        assert!(search.quiescence(-VALUE_INFINITE, VALUE_INFINITE, 0).unwrap() > 0);
+    }
+
+    #[test]
+    fn mate_and_stalemate() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+
+        search.position.set("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1".to_string());
+        assert_eq!(search.search(-VALUE_INFINITE, VALUE_INFINITE, 1, 0), Some(VALUE_DRAW));
+
+        search.position.set("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1".to_string());
+        assert_eq!(
+            search.search(-VALUE_INFINITE, VALUE_INFINITE, 1, 3),
+            Some(-VALUE_MATE + 3)
+        );
+    }
+
+    #[test]
+    fn move_picker_stages() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+
+        position.set("4k3/8/8/3p4/4P3/8/8/R3K3 w - - 0 1".to_string());
+
+        let movelist = movegen.legal_moves(&position);
+        let find = |name: &str| *movelist.iter().find(|mv| format!("{:?}", mv) == name).unwrap();
+        let mut picker = MovePicker::new(&position, find("a1a8"), [find("e4e5"), Move::none()]);
+        let mut picked: Vec<Move> = Vec::new();
+
+        while let Some(mv) = picker.next(&position, &movegen, &History::new()) {
+            picked.push(mv);
+        }
+
+        // Hash move, then captures, then killers, then the remaining quiet moves
+        assert!(picked[0..3] == [find("a1a8"), find("e4d5"), find("e4e5")]);
+        assert_eq!(picked.len(), movelist.len());
+        assert!(movelist.iter().all(|mv| picked.contains(mv)));
+    }
+
+    #[test]
+    fn threads_find_mate() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+
+        search
+            .position
+            .set("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4".to_string());
+        search.options.threads = 3;
+        // Deep enough for the helpers to complete an iteration before the main thread stops them
+        limits.depth = 12;
+        search.run(limits);
+
+        assert_eq!(search.helpers.len(), 2);
+        assert!(search.helpers.iter().all(|helper| helper.completed_depth > 0));
+        assert!(search.total_nodes() > search.nodes_searched);
+        assert_eq!(format!("{:?}", search.root_moves[0].mv), "h5f7");
+        assert_eq!(search.root_moves[0].score, VALUE_MATE - 1);
+    }
+
+    #[test]
+    fn principal_variation() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+
+        search.position.set(MATE_IN_TWO.to_string());
+        limits.depth = 4;
+        search.run(limits);
+
+        // The whole line is kept, down to the mate
+        let line: Vec<Move> = search.root_moves[0].pv.clone();
+        assert_eq!(line.len(), 3);
+        assert_eq!(search.root_moves[0].mv, line[0]);
+
+        for mv in line.iter() {
+            assert!(search.movegen.legal_moves(&search.position).contains(mv));
+            search.position.do_move(*mv);
+        }
+
+        assert!(search.position.in_check());
+        assert!(search.movegen.legal_moves(&search.position).is_empty());
+    }
+
+    #[test]
+    fn stop_infinite_search() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+        let stop = Arc::clone(&search.signals.stop);
+
+        search.position.set(FEN_START_POSITION.to_string());
+        limits.depth = u8::MAX;
+        limits.infinite = true;
+
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let start = Instant::now();
+        search.run(limits);
+        stopper.join().unwrap();
+
+        // The search only ends on stop, with the best move of the last completed iteration
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(search.completed_depth > 0);
+        assert!(search.movegen.legal_moves(&search.position).contains(&search.root_moves[0].mv));
+    }
+
+    #[test]
+    fn ponderhit_starts_the_clock() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+        let ponder = Arc::clone(&search.signals.ponder);
+
+        search.position.set(FEN_START_POSITION.to_string());
+        limits.depth = u8::MAX;
+        limits.movetime = 200;
+        limits.ponder = true;
+        ponder.store(true, Ordering::Relaxed);
+
+        let ponderhit = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(400));
+            ponder.store(false, Ordering::Relaxed);
+        });
+        let start = Instant::now();
+        search.run(limits);
+        ponderhit.join().unwrap();
+
+        // The move time is only counted from the ponderhit
+        assert!(start.elapsed() >= Duration::from_millis(400 + 150));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(search.completed_depth > 0);
+    }
+
+    #[test]
+    fn nodes_and_mate_limits() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+
+        search.position.set(FEN_START_POSITION.to_string());
+        limits.depth = u8::MAX;
+        limits.nodes = 20000;
+        search.run(limits.clone());
+
+        // The node count is checked at every node
+        assert!(search.total_nodes() <= 20000);
+        assert!(search.completed_depth > 0);
+
+        // The search ends once a mate within the given number of moves is found
+        search.position.set(MATE_IN_TWO.to_string());
+        limits.nodes = usize::MAX;
+        limits.mate = 2;
+        search.run(limits);
+
+        assert_eq!(format!("{:?}", search.root_moves[0].mv), "a1a6");
+        assert_eq!(search.root_moves[0].score, VALUE_MATE - 3);
+    }
+
+    #[test]
+    fn time_limits() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+        let mut elapsed = |limits: &SearchLimits| {
+            let start = Instant::now();
+            search.position.set(FEN_START_POSITION.to_string());
+            search.run(limits.clone());
+            return start.elapsed();
+        };
+
+        limits.depth = u8::MAX;
+        limits.movetime = 300;
+        let movetime = elapsed(&limits);
+        // Less the move overhead
+        assert!(movetime >= Duration::from_millis(250) && movetime < Duration::from_millis(1000));
+
+        // The whole clock can be spent on the last move before the time control, but not
+        // on one of the next 40 moves
+        limits.movetime = usize::MAX;
+        limits.white_time = 2000;
+        limits.moves_to_go = 40;
+        let one_of_many = elapsed(&limits);
+        limits.moves_to_go = 1;
+        let last_move = elapsed(&limits);
+        assert!(one_of_many < Duration::from_millis(600));
+        assert!(last_move > one_of_many);
+    }
+
+    #[test]
+    fn move_overhead() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+
+        search.position.set(FEN_START_POSITION.to_string());
+        search.options.move_overhead = 400;
+        limits.depth = u8::MAX;
+        limits.movetime = 500;
+
+        let start = Instant::now();
+        search.run(limits.clone());
+
+        // The overhead is taken from the budget, which is a hard limit checked inside the iterations
+        assert!(start.elapsed() < Duration::from_millis(300));
+        assert!(search.completed_depth > 0);
+    }
+
+    #[test]
+    fn multi_pv_lines() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+
+        // Only one move mates, the other ones are searched as separate lines
+        search.position.set(MATE_IN_TWO.to_string());
+        search.options.multi_pv = 3;
+        limits.depth = 4;
+        search.run(limits);
+
+        let lines = &search.root_moves[0..3];
+
+        assert_eq!(format!("{:?}", lines[0].mv), "a1a6");
+        assert_eq!(lines[0].score, VALUE_MATE - 3);
+        assert!(lines[1].mv != lines[2].mv && lines[1].mv != lines[0].mv && lines[2].mv != lines[0].mv);
+
+        // The other lines are ordered by score, far from a mate
+        for pair in lines.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+        }
+
+        assert!(lines[1].score < VALUE_MATE_IN_MAX_PLY);
+
+        for line in lines.iter() {
+            assert!(line.score > -VALUE_INFINITE);
+            assert_eq!(line.pv[0], line.mv);
+        }
+    }
+
+    #[test]
+    fn search_moves() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+        let mut limits = SearchLimits::default();
+
+        // The mating move is left out
+        search.position.set(MATE_IN_TWO.to_string());
+        limits.depth = 4;
+        let search_moves: Vec<Move> = search
+            .movegen
+            .legal_moves(&search.position)
+            .iter()
+            .filter(|mv| ["a1a5", "c8d7"].contains(&format!("{:?}", mv).as_str()))
+            .copied()
+            .collect();
+        limits.search_moves = Some(search_moves.clone());
+        search.run(limits.clone());
+
+        assert_eq!(search.root_moves.len(), 2);
+        assert!(search_moves.contains(&search.root_moves[0].mv));
+        assert!(search.root_moves[0].score < VALUE_MATE_IN_MAX_PLY);
+
+        // None of the moves given was valid, there is no move to play
+        limits.search_moves = Some(Vec::new());
+        search.run(limits.clone());
+
+        assert!(search.root_moves.is_empty());
+    }
+
     #[test]
     fn perft_edwards_bis() {
-        let bitboards = Rc::new(Bitboards::new());
-        let movegen = Movegen::new(Rc::clone(&bitboards));
-        let position = Position::new(Rc::clone(&bitboards));
-        let mut search = Search::new(position, movegen, Eval::new());
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let mut search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
 
         search
             .position
@@ -1,39 +1,155 @@
 use std::{
     cmp,
+    sync::atomic::Ordering,
     time::{self, Instant},
 };
 
-use crate::{defs::Side, search::defs::SearchLimits};
+use crate::{
+    defs::Side,
+    search::defs::{SearchLimits, Signals},
+};
+
+const DEFAULT_MOVES_TO_GO: usize = 40;
+const MIN_MOVES_TO_GO: usize = 10;
+// Milliseconds kept on the clock when allocating time
+const TIME_MARGIN: u64 = 50;
+// How many times the optimum time the search can last when the position is unclear
+const MAXIMUM_TIME_RATIO: u64 = 5;
 
 pub struct TimeManager {
     start_time: Instant,
-    cutoff: Option<Instant>,
+    // Start of our clock, which differs from the start of the search when pondering
+    clock_start: Instant,
+    optimum: Option<time::Duration>,
+    maximum: Option<time::Duration>,
+    ponder: bool,
+    signals: Signals,
 }
 
 impl TimeManager {
-    pub fn new(limits: SearchLimits, side_to_move: Side, game_ply: usize) -> Self {
-        let think_time: u64 = limits.time(side_to_move) / cmp::max(40 - game_ply, 3) as u64;
+    pub fn new(
+        limits: &SearchLimits,
+        side_to_move: Side,
+        game_ply: usize,
+        move_overhead: u64,
+        signals: Signals,
+    ) -> Self {
         let start_time = time::Instant::now();
-        let cutoff = start_time + time::Duration::from_millis(think_time);
+        let (optimum, maximum) = match TimeManager::allocate(limits, side_to_move, game_ply, move_overhead) {
+            Some((optimum, maximum)) => (
+                Some(time::Duration::from_millis(optimum)),
+                Some(time::Duration::from_millis(maximum)),
+            ),
+            None => (None, None),
+        };
 
         return Self {
             start_time: start_time,
-            cutoff: Some(cutoff),
+            clock_start: start_time,
+            optimum: optimum,
+            maximum: maximum,
+            ponder: limits.ponder,
+            signals: signals,
+        };
+    }
+
+    // Optimum and maximum time to spend on the current move in milliseconds, if the search
+    // is limited by time. Without 'movestogo' the game is assumed to last for a number of
+    // moves decreasing with the game length, and most of the increment is spent on top of
+    // the base time. The move overhead is removed from every budget to absorb GUI latency.
+    fn allocate(limits: &SearchLimits, side_to_move: Side, game_ply: usize, move_overhead: u64) -> Option<(u64, u64)> {
+        if limits.movetime != usize::MAX {
+            let movetime = (limits.movetime as u64).saturating_sub(move_overhead).max(1);
+
+            return Some((movetime, movetime));
+        }
+
+        let time: u64 = limits.time(side_to_move);
+
+        if time == u64::MAX {
+            return None;
+        }
+
+        // Never use the whole clock, some time is needed to send the move
+        let available: u64 = time.saturating_sub(move_overhead + TIME_MARGIN).max(1);
+        let increment: u64 = limits.increment(side_to_move) as u64;
+        let moves_to_go: u64 = match limits.moves_to_go {
+            0 => cmp::max(DEFAULT_MOVES_TO_GO.saturating_sub(game_ply / 2), MIN_MOVES_TO_GO) as u64,
+            moves_to_go => moves_to_go as u64,
         };
+        let optimum: u64 = cmp::min(available / moves_to_go + increment * 3 / 4, available);
+        let maximum: u64 = cmp::min(optimum * MAXIMUM_TIME_RATIO, available);
+
+        return Some((optimum, maximum));
     }
 
-    pub fn default() -> Self {
+    pub fn default(signals: Signals) -> Self {
         Self {
             start_time: time::Instant::now(),
-            cutoff: None,
+            clock_start: time::Instant::now(),
+            optimum: None,
+            maximum: None,
+            ponder: false,
+            signals: signals,
+        }
+    }
+
+    // Milliseconds since the start of the search
+    pub fn elapsed(&self) -> u128 {
+        return self.start_time.elapsed().as_millis();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        return self.signals.stop.load(Ordering::Relaxed);
+    }
+
+    // The clock is not running while pondering, it starts on ponderhit
+    pub fn is_pondering(&self) -> bool {
+        return self.signals.ponder.load(Ordering::Relaxed);
+    }
+
+    // Hard limit, checked inside the search
+    pub fn should_stop(&mut self) -> bool {
+        if self.is_stopped() {
+            return true;
         }
+
+        if !self.clock_running() {
+            return false;
+        }
+
+        if let Some(maximum) = self.maximum {
+            return self.clock_start.elapsed() >= maximum;
+        }
+
+        return false;
     }
 
-    pub fn should_stop(&self) -> bool {
-        if let Some(cutoff) = self.cutoff {
-            return time::Instant::now() >= cutoff;
+    // Soft limit, checked between iterations as a new iteration is unlikely to finish
+    // past this point. The optimum time is scaled by how unstable the search is.
+    pub fn should_stop_iteration(&mut self, scale: f64) -> bool {
+        if !self.clock_running() {
+            return false;
+        }
+
+        if let Some(optimum) = self.optimum {
+            return self.clock_start.elapsed() >= optimum.mul_f64(scale);
         }
 
         return false;
     }
+
+    fn clock_running(&mut self) -> bool {
+        if self.is_pondering() {
+            return false;
+        }
+
+        // Our clock only starts running once the GUI confirms the ponder move
+        if self.ponder {
+            self.ponder = false;
+            self.clock_start = time::Instant::now();
+        }
+
+        return true;
+    }
 }
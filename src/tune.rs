@@ -0,0 +1,528 @@
+mod test;
+
+use std::fs;
+
+use crate::{
+    bitboards::{defs::*, Bitboards},
+    defs::*,
+    evaluate::{
+        defs::{Score, MAX_PHASE},
+        tables::{KING_ATTACKERS_SCALE, MOBILITY_CENTER},
+        weights::Weights,
+        Eval, PIECES,
+    },
+    misc::bits,
+    movegen::defs::pawn_push,
+    position::Position,
+};
+
+// Texel tuning of the classical evaluation. Every term is a weight counted a number of
+// times in the position, so the evaluation is a linear trace of the weights. What the
+// trace misses, the rounding of the king safety and of the taper, is kept as a constant.
+// The weights are then fitted with gradient descent so that the evaluation predicts the
+// game results.
+struct Parameter;
+impl Parameter {
+    // The piece values, followed by the 64 squares of the table of each piece type
+    const PIECE_VALUES: usize = 0;
+    const PIECE_SQUARES: usize = NrOf::PIECE_TYPES;
+    const MOBILITY: usize = Parameter::PIECE_SQUARES + NrOf::PIECE_TYPES * NrOf::SQUARES;
+    const DOUBLED_PAWN: usize = Parameter::MOBILITY + NrOf::PIECE_TYPES;
+    const ISOLATED_PAWN: usize = Parameter::DOUBLED_PAWN + 1;
+    const BACKWARD_PAWN: usize = Parameter::ISOLATED_PAWN + 1;
+    const PASSED_PAWN: usize = Parameter::BACKWARD_PAWN + 1;
+    const PASSED_PAWN_FREE: usize = Parameter::PASSED_PAWN + 8;
+    const KING_ATTACK: usize = Parameter::PASSED_PAWN_FREE + 1;
+    const BISHOP_PAIR: usize = Parameter::KING_ATTACK + NrOf::PIECE_TYPES;
+    const ROOK_OPEN_FILE: usize = Parameter::BISHOP_PAIR + 1;
+    const ROOK_SEMI_OPEN_FILE: usize = Parameter::ROOK_OPEN_FILE + 1;
+    const TEMPO: usize = Parameter::ROOK_SEMI_OPEN_FILE + 1;
+}
+
+const PARAMETERS: usize = Parameter::TEMPO + 1;
+
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+pub const DEFAULT_EPOCHS: usize = 500;
+const REPORT_INTERVAL: usize = 50;
+
+const PIECE_NAMES: [&str; NrOf::PIECE_TYPES] = ["NONE", "PAWN", "KNIGHT", "BISHOP", "ROOK", "QUEEN", "KING"];
+
+struct TuneEntry {
+    // Parameters used by the position, counted positively for white and negatively for black
+    coefficients: Vec<(u16, f64)>,
+    // Middle game weight of the taper, between 0 and 1
+    phase: f64,
+    // Terms of the evaluation which are not tuned, from white's point of view
+    fixed: f64,
+    // 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+pub struct Tuner {
+    entries: Vec<TuneEntry>,
+    mg: Vec<f64>,
+    eg: Vec<f64>,
+    // Converts centipawns to a winning probability, fitted to the data
+    scaling: f64,
+}
+
+impl Tuner {
+    // Starts from the weights of the evaluation
+    pub fn new(weights: &Weights) -> Self {
+        let mut weights: Weights = weights.clone();
+        let scores: Vec<&mut Score> = parameters(&mut weights);
+        let mg: Vec<f64> = scores.iter().map(|score| score.mg as f64).collect();
+        let eg: Vec<f64> = scores.iter().map(|score| score.eg as f64).collect();
+
+        return Self {
+            entries: Vec::new(),
+            mg: mg,
+            eg: eg,
+            scaling: 1.0,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    // Reads one labelled position per line, returns the number of lines which were skipped
+    pub fn load(&mut self, path: &str, eval: &mut Eval, position: &mut Position) -> Result<usize, String> {
+        let content = fs::read_to_string(path).map_err(|error| format!("Cannot read {}: {}", path, error))?;
+        let mut skipped: usize = 0;
+
+        for line in content.lines() {
+            match parse_entry(line) {
+                Some((fen, result)) => self.add(eval, position, fen, result),
+                None => skipped += 1,
+            }
+        }
+
+        return Ok(skipped);
+    }
+
+    // Positions are expected to be quiet, as the static evaluation is compared to the result
+    pub fn add(&mut self, eval: &mut Eval, position: &mut Position, fen: String, result: f64) {
+        position.set(fen);
+
+        let coefficients: Vec<(u16, f64)> = trace(position);
+        let phase: f64 = eval.phase(position).min(MAX_PHASE) as f64 / MAX_PHASE as f64;
+        let score: f64 = match position.side_to_move {
+            Sides::WHITE => eval.evaluate(position) as f64,
+            _ => -eval.evaluate(position) as f64,
+        };
+        let mut entry = TuneEntry {
+            coefficients: coefficients,
+            phase: phase,
+            fixed: 0.0,
+            result: result,
+        };
+
+        entry.fixed = score - self.linear(&entry);
+        self.entries.push(entry);
+    }
+
+    // The loss is convex in the scaling constant, so a golden section search finds its minimum
+    pub fn fit_scaling(&mut self) -> f64 {
+        let ratio: f64 = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high): (f64, f64) = (0.0, 10.0);
+
+        while high - low > 1e-4 {
+            let left: f64 = high - ratio * (high - low);
+            let right: f64 = low + ratio * (high - low);
+
+            self.scaling = left;
+            let left_loss = self.loss();
+            self.scaling = right;
+            let right_loss = self.loss();
+
+            if left_loss < right_loss {
+                high = right;
+            } else {
+                low = left;
+            }
+        }
+
+        self.scaling = (low + high) / 2.0;
+
+        return self.scaling;
+    }
+
+    // Mean logistic loss of the predicted results
+    pub fn loss(&self) -> f64 {
+        let mut loss: f64 = 0.0;
+
+        for entry in self.entries.iter() {
+            let probability = self.probability(entry.fixed + self.linear(entry));
+
+            loss -= entry.result * probability.ln() + (1.0 - entry.result) * (1.0 - probability).ln();
+        }
+
+        return loss / self.entries.len().max(1) as f64;
+    }
+
+    // Full batch gradient descent with Adam, as the parameters are used at very different rates
+    pub fn run(&mut self, epochs: usize) {
+        let mut moments: [Vec<f64>; 4] = [
+            vec![0.0; PARAMETERS],
+            vec![0.0; PARAMETERS],
+            vec![0.0; PARAMETERS],
+            vec![0.0; PARAMETERS],
+        ];
+        let derivative: f64 = self.scaling * std::f64::consts::LN_10 / 400.0;
+
+        for epoch in 1..=epochs {
+            let mut gradient_mg: Vec<f64> = vec![0.0; PARAMETERS];
+            let mut gradient_eg: Vec<f64> = vec![0.0; PARAMETERS];
+
+            for entry in self.entries.iter() {
+                let probability = self.probability(entry.fixed + self.linear(entry));
+                let error = (probability - entry.result) * derivative / self.entries.len() as f64;
+
+                for &(index, coefficient) in entry.coefficients.iter() {
+                    gradient_mg[index as usize] += error * coefficient * entry.phase;
+                    gradient_eg[index as usize] += error * coefficient * (1.0 - entry.phase);
+                }
+            }
+
+            let [mg_mean, mg_variance, eg_mean, eg_variance] = &mut moments;
+            adam(&mut self.mg, &gradient_mg, mg_mean, mg_variance, epoch);
+            adam(&mut self.eg, &gradient_eg, eg_mean, eg_variance, epoch);
+
+            if epoch % REPORT_INTERVAL == 0 || epoch == epochs {
+                println!("Epoch {:>5} loss {:.6}", epoch, self.loss());
+            }
+        }
+    }
+
+    pub fn weights(&self) -> Weights {
+        let mut weights = Weights::default();
+
+        for (index, score) in parameters(&mut weights).into_iter().enumerate() {
+            *score = Score::new(self.mg[index].round() as i16, self.eg[index].round() as i16);
+        }
+
+        return weights;
+    }
+
+    // The tuned weights, laid out as in evaluate/tables.rs
+    pub fn tables_source(&self) -> String {
+        let weights: Weights = self.weights();
+        let mut source: String = format!(
+            "// Tuned on {} positions with a scaling constant of {:.4}\n\n",
+            self.entries.len(),
+            self.scaling
+        );
+
+        for (phase, mg) in [("MG", true), ("EG", false)] {
+            let values: Vec<String> = weights
+                .piece_values
+                .iter()
+                .map(|score| phase_value(*score, mg).to_string())
+                .collect();
+
+            source += &format!(
+                "pub const PIECE_VALUES_{}: [i16; NrOf::PIECE_TYPES] = [{}];\n",
+                phase,
+                values.join(", ")
+            );
+        }
+
+        source += "\n";
+        source += &array_source("MOBILITY_WEIGHTS", "NrOf::PIECE_TYPES", &weights.mobility);
+        source += "\n";
+        source += &score_source("DOUBLED_PAWN", weights.doubled_pawn);
+        source += &score_source("ISOLATED_PAWN", weights.isolated_pawn);
+        source += &score_source("BACKWARD_PAWN", weights.backward_pawn);
+        source += &array_source("PASSED_PAWN", "8", &weights.passed_pawn);
+        source += &score_source("PASSED_PAWN_FREE", weights.passed_pawn_free);
+        source += "\n";
+        source += &array_source("KING_ATTACK_WEIGHTS", "NrOf::PIECE_TYPES", &weights.king_attack);
+        source += "\n";
+        source += &score_source("BISHOP_PAIR", weights.bishop_pair);
+        source += &score_source("ROOK_OPEN_FILE", weights.rook_open_file);
+        source += &score_source("ROOK_SEMI_OPEN_FILE", weights.rook_semi_open_file);
+        source += &score_source("TEMPO", weights.tempo);
+
+        for (name, table) in PIECE_NAMES.iter().zip(weights.piece_squares.iter()).skip(PieceType::PAWN) {
+            for (phase, mg) in [("MG", true), ("EG", false)] {
+                source += &format!(
+                    "\n#[rustfmt::skip]\n#[allow(clippy::zero_prefixed_literal)]\nconst {}_{}_TABLE: [i16; NrOf::SQUARES] = [\n",
+                    name, phase
+                );
+
+                for rank in table.chunks(8) {
+                    let row: Vec<String> = rank
+                        .iter()
+                        .map(|score| format!("{:04}", phase_value(*score, mg)))
+                        .collect();
+
+                    source += &format!("    {},\n", row.join(", "));
+                }
+
+                source += "];\n";
+            }
+        }
+
+        return source;
+    }
+
+    fn linear(&self, entry: &TuneEntry) -> f64 {
+        let mut mg: f64 = 0.0;
+        let mut eg: f64 = 0.0;
+
+        for &(index, coefficient) in entry.coefficients.iter() {
+            mg += coefficient * self.mg[index as usize];
+            eg += coefficient * self.eg[index as usize];
+        }
+
+        return mg * entry.phase + eg * (1.0 - entry.phase);
+    }
+
+    // Kept away from 0 and 1 so that the logarithms of the loss stay finite
+    fn probability(&self, score: f64) -> f64 {
+        return (1.0 / (1.0 + 10f64.powf(-self.scaling * score / 400.0))).clamp(1e-9, 1.0 - 1e-9);
+    }
+}
+
+fn adam(weights: &mut [f64], gradient: &[f64], mean: &mut [f64], variance: &mut [f64], epoch: usize) {
+    let mean_correction: f64 = 1.0 - BETA1.powi(epoch as i32);
+    let variance_correction: f64 = 1.0 - BETA2.powi(epoch as i32);
+
+    for i in 0..weights.len() {
+        mean[i] = BETA1 * mean[i] + (1.0 - BETA1) * gradient[i];
+        variance[i] = BETA2 * variance[i] + (1.0 - BETA2) * gradient[i] * gradient[i];
+        weights[i] -=
+            LEARNING_RATE * (mean[i] / mean_correction) / ((variance[i] / variance_correction).sqrt() + EPSILON);
+    }
+}
+
+// Scores of the weights, in the order of the parameters
+fn parameters(weights: &mut Weights) -> Vec<&mut Score> {
+    let mut scores: Vec<&mut Score> = weights.piece_values.iter_mut().collect();
+
+    scores.extend(weights.piece_squares.iter_mut().flatten());
+    scores.extend(weights.mobility.iter_mut());
+    scores.extend([&mut weights.doubled_pawn, &mut weights.isolated_pawn, &mut weights.backward_pawn]);
+    scores.extend(weights.passed_pawn.iter_mut());
+    scores.push(&mut weights.passed_pawn_free);
+    scores.extend(weights.king_attack.iter_mut());
+    scores.extend([
+        &mut weights.bishop_pair,
+        &mut weights.rook_open_file,
+        &mut weights.rook_semi_open_file,
+        &mut weights.tempo,
+    ]);
+
+    #[cfg(debug_assertions)]
+    assert_eq!(scores.len(), PARAMETERS);
+
+    return scores;
+}
+
+fn table_index(piece_type: Piece, index: usize) -> usize {
+    return Parameter::PIECE_SQUARES + piece_type * NrOf::SQUARES + index;
+}
+
+fn phase_value(score: Score, mg: bool) -> i16 {
+    return match mg {
+        true => score.mg,
+        false => score.eg,
+    };
+}
+
+fn score_source(name: &str, score: Score) -> String {
+    return format!("pub const {}: Score = {};\n", name, score_value(score));
+}
+
+fn array_source(name: &str, size: &str, scores: &[Score]) -> String {
+    let mut source: String = format!("pub const {}: [Score; {}] = [\n", name, size);
+
+    for score in scores {
+        source += &format!("    {},\n", score_value(*score));
+    }
+
+    return source + "];\n";
+}
+
+fn score_value(score: Score) -> String {
+    return match score == Score::ZERO {
+        true => "Score::ZERO".to_string(),
+        false => format!("Score::new({}, {})", score.mg, score.eg),
+    };
+}
+
+// Same terms as the classical evaluation, the tables being laid out as seen by white
+fn trace(position: &Position) -> Vec<(u16, f64)> {
+    let mut coefficients: [f64; PARAMETERS] = [0.0; PARAMETERS];
+
+    for square in RangeOf::SQUARES {
+        let piece: Piece = position.piece_on(square);
+
+        if piece == PieceType::NONE {
+            continue;
+        }
+
+        let piece_type: Piece = type_of_piece(piece);
+        let (coefficient, index): (f64, Square) = match color_of_piece(piece) {
+            Sides::WHITE => (1.0, square ^ 56),
+            _ => (-1.0, square),
+        };
+
+        coefficients[Parameter::PIECE_VALUES + piece_type] += coefficient;
+        coefficients[table_index(piece_type, index)] += coefficient;
+    }
+
+    for side in [Sides::WHITE, Sides::BLACK] {
+        let sign: f64 = match side {
+            Sides::WHITE => 1.0,
+            _ => -1.0,
+        };
+
+        trace_mobility(&mut coefficients, position, side, sign);
+        trace_pawns(&mut coefficients, position, side, sign);
+        trace_king_safety(&mut coefficients, position, side, sign);
+        trace_pieces(&mut coefficients, position, side, sign);
+    }
+
+    coefficients[Parameter::TEMPO] = match position.side_to_move {
+        Sides::WHITE => 1.0,
+        _ => -1.0,
+    };
+
+    return coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, &coefficient)| coefficient != 0.0)
+        .map(|(index, &coefficient)| (index as u16, coefficient))
+        .collect();
+}
+
+fn trace_mobility(coefficients: &mut [f64], position: &Position, side: Side, sign: f64) {
+    let bitboards: &Bitboards = position.bitboards();
+    let occupied: Bitboard = position.by_color_bb[Sides::BOTH];
+    let area: Bitboard =
+        !position.by_color_bb[side] & !pawn_attacks_bb(side ^ 1, position.by_type_bb[side ^ 1][PieceType::PAWN]);
+
+    for piece_type in PIECES {
+        let mut pieces: Bitboard = position.by_type_bb[side][piece_type];
+
+        while pieces != EMPTY {
+            let square: Square = bits::pop(&mut pieces);
+            let moves = (bitboards.attack_bb(piece_type, square, occupied) & area).count_ones() as i16;
+
+            coefficients[Parameter::MOBILITY + piece_type] += sign * (moves - MOBILITY_CENTER[piece_type]) as f64;
+        }
+    }
+}
+
+// Pawn structure and passed pawns
+fn trace_pawns(coefficients: &mut [f64], position: &Position, side: Side, sign: f64) {
+    let our_pawns: Bitboard = position.by_type_bb[side][PieceType::PAWN];
+    let their_pawns: Bitboard = position.by_type_bb[side ^ 1][PieceType::PAWN];
+    let their_attacks: Bitboard = pawn_attacks_bb(side ^ 1, their_pawns);
+    let mut pawns: Bitboard = our_pawns;
+
+    while pawns != EMPTY {
+        let square: Square = bits::pop(&mut pawns);
+        let stop_square: Square = (square as isize + pawn_push(side)) as Square;
+
+        if forward_file_bb(side, square) & our_pawns != EMPTY {
+            coefficients[Parameter::DOUBLED_PAWN] += sign;
+        }
+
+        if adjacent_files_bb(square) & our_pawns == EMPTY {
+            coefficients[Parameter::ISOLATED_PAWN] += sign;
+        } else if adjacent_files_bb(square) & !forward_ranks_bb(side, square) & our_pawns == EMPTY
+            && their_attacks & square_bb(stop_square) != EMPTY
+        {
+            coefficients[Parameter::BACKWARD_PAWN] += sign;
+        }
+
+        if passed_pawn_span(side, square) & their_pawns == EMPTY && forward_file_bb(side, square) & our_pawns == EMPTY {
+            coefficients[Parameter::PASSED_PAWN + relative_rank(side, square)] += sign;
+
+            if position.piece_on(stop_square) == PieceType::NONE {
+                coefficients[Parameter::PASSED_PAWN_FREE] += sign * relative_rank(side, square) as f64;
+            }
+        }
+    }
+}
+
+// The attacks on the king zone are scaled with the number of attackers, which is known
+// once all the pieces are seen
+fn trace_king_safety(coefficients: &mut [f64], position: &Position, side: Side, sign: f64) {
+    let bitboards: &Bitboards = position.bitboards();
+    let occupied: Bitboard = position.by_color_bb[Sides::BOTH];
+    let king_square: Square = bits::lsb(position.by_type_bb[side][PieceType::KING]);
+    let king_zone: Bitboard = bitboards.attack_bb(PieceType::KING, king_square, EMPTY) | square_bb(king_square);
+    let mut attackers: usize = 0;
+    let mut attacked: [u32; NrOf::PIECE_TYPES] = [0; NrOf::PIECE_TYPES];
+
+    for piece_type in PIECES {
+        let mut pieces: Bitboard = position.by_type_bb[side ^ 1][piece_type];
+
+        while pieces != EMPTY {
+            let square: Square = bits::pop(&mut pieces);
+            let squares: u32 = (bitboards.attack_bb(piece_type, square, occupied) & king_zone).count_ones();
+
+            if squares > 0 {
+                attackers += 1;
+                attacked[piece_type] += squares;
+            }
+        }
+    }
+
+    let scale: f64 = KING_ATTACKERS_SCALE[attackers.min(KING_ATTACKERS_SCALE.len() - 1)] as f64 / 100.0;
+
+    for piece_type in PIECES {
+        coefficients[Parameter::KING_ATTACK + piece_type] += sign * attacked[piece_type] as f64 * scale;
+    }
+}
+
+fn trace_pieces(coefficients: &mut [f64], position: &Position, side: Side, sign: f64) {
+    let our_pawns: Bitboard = position.by_type_bb[side][PieceType::PAWN];
+    let their_pawns: Bitboard = position.by_type_bb[side ^ 1][PieceType::PAWN];
+    let mut rooks: Bitboard = position.by_type_bb[side][PieceType::ROOK];
+
+    if position.by_type_bb[side][PieceType::BISHOP].count_ones() >= 2 {
+        coefficients[Parameter::BISHOP_PAIR] += sign;
+    }
+
+    while rooks != EMPTY {
+        let file: Bitboard = file_bb(bits::pop(&mut rooks));
+
+        if file & our_pawns == EMPTY {
+            match file & their_pawns == EMPTY {
+                true => coefficients[Parameter::ROOK_OPEN_FILE] += sign,
+                false => coefficients[Parameter::ROOK_SEMI_OPEN_FILE] += sign,
+            }
+        }
+    }
+}
+
+// Accepts the usual formats of the tuning sets: a FEN followed by the result as
+// "1-0", "0-1" or "1/2-1/2", possibly quoted, or as [1.0], [0.5] or [0.0]
+fn parse_entry(line: &str) -> Option<(String, f64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    if fields.len() < 5 {
+        return None;
+    }
+
+    // The move counters of the FEN are ignored, they do not change the evaluation
+    let result: f64 = fields[4..]
+        .iter()
+        .find_map(|field| match field.trim_end_matches(';').trim_matches('"') {
+            "1-0" | "[1.0]" | "[1]" => Some(1.0),
+            "1/2-1/2" | "[0.5]" => Some(0.5),
+            "0-1" | "[0.0]" | "[0]" => Some(0.0),
+            _ => None,
+        })?;
+
+    return Some((fields[0..4].join(" "), result));
+}
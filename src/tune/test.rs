@@ -0,0 +1,90 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        benchmark::FENS,
+        bitboards::Bitboards,
+        defs::PieceType,
+        evaluate::{weights::Weights, Eval},
+        position::Position,
+        tune::{parse_entry, Parameter, Tuner},
+    };
+
+    #[test]
+    fn tune_parse_entry() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3";
+
+        assert_eq!(parse_entry(&format!("{} 0 1 [1.0]", fen)), Some((fen.to_string(), 1.0)));
+        assert_eq!(parse_entry(&format!("{} c9 \"1/2-1/2\";", fen)), Some((fen.to_string(), 0.5)));
+        assert_eq!(parse_entry(&format!("{} 0 1 0-1", fen)), Some((fen.to_string(), 0.0)));
+        assert_eq!(parse_entry(&format!("{} 0 1", fen)), None);
+        assert_eq!(parse_entry(""), None);
+    }
+
+    #[test]
+    fn tune_reduces_loss() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let mut tuner = Tuner::new(&Weights::default());
+
+        // Before tuning, the weights are written as they are in the evaluation
+        for line in tuner.tables_source().lines().skip(1) {
+            assert!(include_str!("../evaluate/tables.rs").contains(line), "{}", line);
+        }
+
+        // Arbitrary results, the tables can still get closer to them
+        for (i, fen) in FENS.iter().enumerate() {
+            let fen: String = fen.split(" moves").next().unwrap().to_string();
+            tuner.add(&mut eval, &mut position, fen, (i % 3) as f64 / 2.0);
+        }
+
+        tuner.fit_scaling();
+        let loss = tuner.loss();
+        tuner.run(20);
+
+        assert!(tuner.loss() < loss);
+        assert!(tuner.weights() != Weights::default());
+    }
+
+    #[test]
+    fn tune_trace() {
+        let bitboards = Arc::new(Bitboards::new());
+        let mut eval = Eval::new(Arc::clone(&bitboards));
+        let mut position = Position::new(Arc::clone(&bitboards));
+        let mut tuner = Tuner::new(&Weights::default());
+
+        // Only the rounding of the king safety and of the taper is left out of the trace
+        for fen in FENS.iter() {
+            let fen: String = fen.split(" moves").next().unwrap().to_string();
+            tuner.add(&mut eval, &mut position, fen.clone(), 0.5);
+
+            assert!(tuner.entries.last().unwrap().fixed.abs() < 3.0, "{}", fen);
+        }
+
+        // Every term of the evaluation is tuned
+        let used: Vec<u16> = tuner
+            .entries
+            .iter()
+            .flat_map(|entry| entry.coefficients.iter().map(|&(index, _)| index))
+            .collect();
+
+        for parameter in [
+            Parameter::MOBILITY + PieceType::QUEEN,
+            Parameter::DOUBLED_PAWN,
+            Parameter::ISOLATED_PAWN,
+            Parameter::BACKWARD_PAWN,
+            Parameter::PASSED_PAWN + 5,
+            Parameter::PASSED_PAWN_FREE,
+            Parameter::KING_ATTACK + PieceType::KNIGHT,
+            Parameter::BISHOP_PAIR,
+            Parameter::ROOK_OPEN_FILE,
+            Parameter::ROOK_SEMI_OPEN_FILE,
+            Parameter::TEMPO,
+        ] {
+            assert!(used.contains(&(parameter as u16)), "{}", parameter);
+        }
+    }
+}
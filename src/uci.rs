@@ -1,15 +1,51 @@
-use std::time;
+mod test;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::Ordering,
+        mpsc::{self, Receiver},
+    },
+    thread, time,
+};
 
 use crate::{
-    evaluate::defs::DEFAULT_HASH_SIZE,
+    defs::Sides,
+    evaluate::{
+        defs::{EvalTrace, Score, DEFAULT_HASH_SIZE, DEFAULT_PAWN_HASH_SIZE, MAX_PHASE},
+        nnue::{Network, CLASSICAL_EVALUATION, EMBEDDED_NETWORK},
+        weights::{Weights, DEFAULT_WEIGHTS},
+    },
+    movegen::defs::Move,
     search::{
-        defs::{SearchLimits, FEN_START_POSITION},
+        defs::{SearchLimits, Signals, FEN_START_POSITION, VALUE_MATE, VALUE_MATE_IN_MAX_PLY},
+        ordering::OrderingStats,
         Search,
     },
 };
 
-use crate::benchmark::FENS;
+use crate::{
+    benchmark::FENS,
+    tune::{Tuner, DEFAULT_EPOCHS},
+};
 
+const GO_PARAMETERS: [&str; 13] = [
+    "perft",
+    "depth",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+    "searchmoves",
+];
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct UCI {}
 
 impl UCI {
@@ -18,18 +54,18 @@ impl UCI {
 
         let argc = std::env::args().len();
         let mut buffer: String = std::env::args().skip(1).collect::<Vec<String>>().join(" ");
+        let receiver: Option<Receiver<String>> = match argc {
+            1 => Some(UCI::spawn_input_thread(search.signals.clone())),
+            _ => None,
+        };
 
         loop {
-            if argc == 1 {
-                let read_result = std::io::stdin().read_line(&mut buffer);
-
-                if read_result.is_err() {
-                    buffer = "quit".to_string();
-                }
+            if let Some(receiver) = &receiver {
+                buffer = receiver.recv().unwrap_or("quit".to_string());
             }
 
             let cmd: String = buffer.clone();
-            let mut args: std::str::SplitWhitespace<'_> = cmd.trim().split_whitespace();
+            let mut args: std::str::SplitWhitespace<'_> = cmd.split_whitespace();
             let mut token = args.next().unwrap_or("");
             buffer.clear();
 
@@ -37,6 +73,16 @@ impl UCI {
                 println!("id name Oxide");
                 println!("id author Theo Dammaretz");
                 println!("option name Hash type spin default 128 min 1 max 512");
+                println!("option name Pawn Hash type spin default 2 min 1 max 64");
+                println!("option name Ponder type check default false");
+                println!("option name Move Overhead type spin default 10 min 0 max 5000");
+                println!("option name Null Move Pruning type check default true");
+                println!("option name Late Move Reductions type check default true");
+                println!("option name Futility Pruning type check default true");
+                println!("option name MultiPV type spin default 1 min 1 max 256");
+                println!("option name Threads type spin default 1 min 1 max 256");
+                println!("option name EvalFile type string default {}", CLASSICAL_EVALUATION);
+                println!("option name EvalWeights type string default {}", DEFAULT_WEIGHTS);
                 println!("uciok");
             } else if token == "xboard" {
                 println!("This engine does not support the xboard protocol.");
@@ -45,17 +91,26 @@ impl UCI {
                 println!("readyok");
             } else if token == "ucinewgame" {
                 search.position.set(FEN_START_POSITION.to_string());
+                search.eval.transposition_table.clear();
+                search.clear_history();
             } else if token == "position" {
                 UCI::position(search, &mut args);
             } else if token == "go" {
                 UCI::go(search, &mut args);
+                search.signals.searching.store(false, Ordering::Relaxed);
             } else if token == "setoption" {
                 UCI::option(search, &mut args);
             } else if token == "bench" {
                 UCI::bench(search);
+            } else if token == "eval" {
+                UCI::eval(search, &mut args);
+            } else if token == "saveweights" {
+                UCI::save_weights(search, &mut args);
+            } else if token == "tune" {
+                UCI::tune(search, &mut args);
             } else if token == "help" {
                 UCI::help();
-            } else if token != "" && token.chars().nth(0).unwrap_or_default() != '#' {
+            } else if !token.is_empty() && token != "quit" && token.chars().nth(0).unwrap_or_default() != '#' {
                 println!("Unknown command: {}. Type help for more information", token);
             }
 
@@ -65,6 +120,54 @@ impl UCI {
         }
     }
 
+    // Reads the standard input on its own thread so that the commands controlling a
+    // running search are handled right away. Other commands are sent to the main loop.
+    fn spawn_input_thread(signals: Signals) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel::<String>();
+
+        thread::spawn(move || loop {
+            let mut command = String::new();
+            // Let the current search finish when the input is closed
+            let closed: bool = !matches!(std::io::stdin().read_line(&mut command), Ok(n) if n > 0);
+
+            if closed {
+                command = "quit".to_string();
+            }
+
+            let token = command.split_whitespace().next().unwrap_or("").to_string();
+
+            match token.as_str() {
+                "stop" => {
+                    // Left over once the search is done, it must not stop the next one
+                    if signals.searching.load(Ordering::Relaxed) {
+                        signals.stop.store(true, Ordering::Relaxed);
+                    }
+                }
+                "ponderhit" => signals.ponder.store(false, Ordering::Relaxed),
+                // Otherwise the main loop answers once the previous commands are processed
+                "isready" if signals.searching.load(Ordering::Relaxed) => println!("readyok"),
+                _ => {
+                    if token == "go" {
+                        // Reset before the search starts so that an early stop or ponderhit is not lost
+                        signals.stop.store(false, Ordering::Relaxed);
+                        signals
+                            .ponder
+                            .store(command.split_whitespace().any(|t| t == "ponder"), Ordering::Relaxed);
+                        signals.searching.store(true, Ordering::Relaxed);
+                    } else if token == "quit" && !closed && signals.searching.load(Ordering::Relaxed) {
+                        signals.stop.store(true, Ordering::Relaxed);
+                    }
+
+                    if sender.send(command).is_err() || token == "quit" {
+                        break;
+                    }
+                }
+            }
+        });
+
+        return receiver;
+    }
+
     fn position(search: &mut Search, args: &mut std::str::SplitWhitespace<'_>) {
         let mut token = args.next().unwrap_or("");
 
@@ -72,11 +175,11 @@ impl UCI {
             search.position.set(FEN_START_POSITION.to_string());
 
             // Consume the next token if it is 'moves'
-            token = args.next().unwrap_or("");
+            args.next();
         } else if token == "fen" {
             let mut fen = String::new();
 
-            while token != "moves" && token != "" {
+            while token != "moves" && !token.is_empty() {
                 token = args.next().unwrap_or("");
                 fen += token;
                 fen += " ";
@@ -88,31 +191,45 @@ impl UCI {
         // Move to first move if any
         token = args.next().unwrap_or("");
 
-        while token != "" {
-            let mv_string = token.to_ascii_lowercase();
-
-            for mv in search.movegen.legal_moves(&search.position) {
-                if mv_string == format!("{:?}", mv) {
-                    search.position.do_move(mv);
-                    break;
-                }
+        while !token.is_empty() {
+            if let Some(mv) = UCI::parse_move(search, token) {
+                search.position.do_move(mv);
             }
 
             token = args.next().unwrap_or("");
         }
     }
 
+    // Legal move of the current position written in coordinate notation
+    fn parse_move(search: &Search, token: &str) -> Option<Move> {
+        let mv_string = token.to_ascii_lowercase();
+
+        return search
+            .movegen
+            .legal_moves(&search.position)
+            .into_iter()
+            .find(|&mv| mv_string == format!("{:?}", mv));
+    }
+
     fn go(search: &mut Search, args: &mut std::str::SplitWhitespace<'_>) {
+        let limits = UCI::parse_go(search, args);
+
+        search.run(limits);
+    }
+
+    fn parse_go(search: &Search, args: &mut std::str::SplitWhitespace<'_>) -> SearchLimits {
         let mut limits = SearchLimits::default();
+        let mut depth_given = false;
         let mut token = args.next().unwrap_or("");
 
-        while token != "" {
+        while !token.is_empty() {
             match token {
                 "perft" => {
                     limits.perft = args.next().unwrap_or("1").parse::<u8>().unwrap_or(1);
                 }
                 "depth" => {
                     limits.depth = args.next().unwrap_or("1").parse::<u8>().unwrap_or(1);
+                    depth_given = true;
                 }
                 "ponder" => {
                     limits.ponder = true;
@@ -143,6 +260,25 @@ impl UCI {
                 }
                 "infinite" => {
                     limits.depth = u8::MAX;
+                    limits.infinite = true;
+                }
+                "searchmoves" => {
+                    // Without a valid move there is nothing to search
+                    let search_moves: &mut Vec<Move> = limits.search_moves.get_or_insert_with(Vec::new);
+
+                    token = args.next().unwrap_or("");
+
+                    // The list of moves ends with the next parameter
+                    while !token.is_empty() && !GO_PARAMETERS.contains(&token) {
+                        match UCI::parse_move(search, token) {
+                            Some(mv) => search_moves.push(mv),
+                            None => println!("info string Invalid move in searchmoves: {}", token),
+                        }
+
+                        token = args.next().unwrap_or("");
+                    }
+
+                    continue;
                 }
                 _ => (),
             }
@@ -150,25 +286,58 @@ impl UCI {
             token = args.next().unwrap_or("");
         }
 
-        search.run(limits);
+        // Search until the mate is found unless a depth is also given, before or after it
+        if limits.mate > 0 && !depth_given {
+            limits.depth = u8::MAX;
+        }
+
+        return limits;
     }
 
     fn option(search: &mut Search, args: &mut std::str::SplitWhitespace<'_>) {
         let mut token = args.next().unwrap_or("");
-        let mut selected_option = "";
+        let mut selected_option = String::new();
 
-        while token != "" {
+        while !token.is_empty() {
             match token {
                 "name" => {
-                    selected_option = args.next().unwrap_or("");
+                    // Option names can be made of several words
+                    let mut name: Vec<&str> = Vec::new();
+                    token = args.next().unwrap_or("");
+
+                    while !token.is_empty() && token != "value" {
+                        name.push(token);
+                        token = args.next().unwrap_or("");
+                    }
+
+                    selected_option = name.join(" ");
+                    continue;
                 }
                 "value" => {
-                    let value = args.next().unwrap_or("");
+                    // Values can be made of several words too, like file paths
+                    let value: String = args.by_ref().collect::<Vec<&str>>().join(" ");
+                    let value: &str = value.as_str();
 
-                    match selected_option {
+                    match selected_option.as_str() {
                         "Hash" => search.eval.resize_transposition_table(
-                            value.parse::<usize>().unwrap_or(DEFAULT_HASH_SIZE).min(512).max(1),
+                            value.parse::<usize>().unwrap_or(DEFAULT_HASH_SIZE).clamp(1, 512),
                         ),
+                        "Pawn Hash" => search
+                            .eval
+                            .resize_pawn_table(value.parse::<usize>().unwrap_or(DEFAULT_PAWN_HASH_SIZE).clamp(1, 64)),
+                        // Pondering is driven by the GUI with 'go ponder', there is nothing to configure
+                        "Ponder" => (),
+                        "Move Overhead" => search.options.move_overhead = value.parse::<u64>().unwrap_or(10).min(5000),
+                        "Null Move Pruning" => search.options.null_move = value == "true",
+                        "Late Move Reductions" => search.options.late_move_reductions = value == "true",
+                        "Futility Pruning" => search.options.futility = value == "true",
+                        "MultiPV" => search.options.multi_pv = value.parse::<usize>().unwrap_or(1).clamp(1, 256),
+                        "Threads" => search.options.threads = value.parse::<usize>().unwrap_or(1).clamp(1, 256),
+                        "EvalFile" => {
+                            search.options.eval_file = value.to_string();
+                            UCI::load_network(search);
+                        }
+                        "EvalWeights" => UCI::load_weights(search, value),
                         _ => (),
                     }
                 }
@@ -179,32 +348,288 @@ impl UCI {
         }
     }
 
+    // A network that cannot be loaded leaves the classical evaluation in place
+    fn load_network(search: &mut Search) {
+        let network = match search.options.eval_file.as_str() {
+            CLASSICAL_EVALUATION => {
+                search.position.set_network(None);
+                return;
+            }
+            EMBEDDED_NETWORK => Ok(Network::embedded()),
+            path => Network::load(path),
+        };
+
+        match network {
+            Ok(network) => {
+                println!("info string NNUE evaluation using {}", search.options.eval_file);
+                search.position.set_network(Some(Arc::new(network)));
+            }
+            Err(error) => {
+                println!("info string {}, using the classical evaluation", error);
+                search.position.set_network(None);
+            }
+        }
+    }
+
+    // Weights that cannot be loaded leave the current ones in place
+    fn load_weights(search: &mut Search, path: &str) {
+        let weights = match path {
+            DEFAULT_WEIGHTS => Ok(Weights::default()),
+            _ => Weights::load(path),
+        };
+
+        match weights {
+            Ok(weights) => {
+                println!("info string Evaluation weights from {}", path);
+                search.position.set_weights(Arc::new(weights));
+                search.clear_pawn_tables();
+            }
+            Err(error) => println!("info string {}", error),
+        }
+    }
+
+    // eval [json]
+    // Shows the terms of the classical evaluation of the current position for each side,
+    // as a table or as JSON. Scores are in centipawns from white's point of view.
+    fn eval(search: &mut Search, args: &mut std::str::SplitWhitespace<'_>) {
+        let trace: EvalTrace = search.eval.trace(&search.position);
+        let network: Option<i16> = search
+            .position
+            .network()
+            .map(|network| network.evaluate(search.position.accumulator(), Sides::WHITE));
+
+        match args.next() {
+            Some("json") => UCI::print_trace_json(&trace, network),
+            _ => UCI::print_trace(&trace, network),
+        }
+    }
+
+    fn print_trace(trace: &EvalTrace, network: Option<i16>) {
+        let separator = "----------------+-------------+-------------+-------------";
+        let mut total: [Score; 2] = [Score::ZERO; 2];
+
+        println!("     Term       |    White    |    Black    |    Total");
+        println!("                |   MG    EG  |   MG    EG  |   MG    EG");
+        println!("{}", separator);
+
+        for (name, sides) in trace.terms.iter() {
+            let white: Score = sides[Sides::WHITE];
+            let black: Score = sides[Sides::BLACK];
+
+            total[Sides::WHITE] += white;
+            total[Sides::BLACK] += black;
+            println!(
+                " {:<14} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}",
+                name,
+                white.mg,
+                white.eg,
+                black.mg,
+                black.eg,
+                white.mg - black.mg,
+                white.eg - black.eg
+            );
+        }
+
+        let (white, black) = (total[Sides::WHITE], total[Sides::BLACK]);
+
+        println!("{}", separator);
+        println!(
+            " {:<14} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}",
+            "Total",
+            white.mg,
+            white.eg,
+            black.mg,
+            black.eg,
+            white.mg - black.mg,
+            white.eg - black.eg
+        );
+        println!();
+        println!("Phase           : {} / {}", trace.phase, MAX_PHASE);
+        println!("Evaluation      : {} cp (white side)", trace.score);
+
+        if let Some(score) = network {
+            println!("NNUE evaluation : {} cp (white side)", score);
+        }
+    }
+
+    fn print_trace_json(trace: &EvalTrace, network: Option<i16>) {
+        let score = |score: Score| format!("{{\"mg\":{},\"eg\":{}}}", score.mg, score.eg);
+        let terms: Vec<String> = trace
+            .terms
+            .iter()
+            .map(|(name, sides)| {
+                format!(
+                    "\"{}\":{{\"white\":{},\"black\":{}}}",
+                    name.to_lowercase().replace(' ', "_"),
+                    score(sides[Sides::WHITE]),
+                    score(sides[Sides::BLACK])
+                )
+            })
+            .collect();
+        let network: String = match network {
+            Some(score) => score.to_string(),
+            None => "null".to_string(),
+        };
+
+        println!(
+            "{{\"terms\":{{{}}},\"phase\":{},\"max_phase\":{},\"score\":{},\"nnue\":{}}}",
+            terms.join(","),
+            trace.phase,
+            MAX_PHASE,
+            trace.score,
+            network
+        );
+    }
+
+    // saveweights [file]
+    // Writes the weights of the evaluation, to the standard output without a file
+    fn save_weights(search: &mut Search, args: &mut std::str::SplitWhitespace<'_>) {
+        match args.next() {
+            Some(path) => match search.position.weights().save(path) {
+                Ok(()) => println!("Weights written to {}", path),
+                Err(error) => println!("{}", error),
+            },
+            None => print!("{}", search.position.weights().to_text()),
+        }
+    }
+
+    // The search is run a second time with the material computed at every evaluation, to
+    // measure the gain of keeping it up to date in the position. The network evaluation
+    // does not use the material, the second run is skipped with a network.
     fn bench(search: &mut Search) {
+        let (nodes, duration, stats) = UCI::bench_search(search);
+        let pawn_hits = search.eval.pawn_table.hit_rate();
+        let nps: usize = 1000 * nodes / duration.as_millis() as usize;
+        let full_nps: Option<usize> = match search.position.network() {
+            Some(_) => None,
+            None => {
+                search.options.material_from_scratch = true;
+                let (full_nodes, full_duration, _) = UCI::bench_search(search);
+                search.options.material_from_scratch = false;
+
+                Some(1000 * full_nodes / full_duration.as_millis() as usize)
+            }
+        };
+
+        println!("\n===========================");
+        println!("Total time (ms) : {}", duration.as_millis());
+        println!("Nodes searched  : {}", nodes);
+        println!("Nodes/second    : {}", nps);
+        println!("First move cuts : {:.1}%", stats.first_move_rate());
+        println!("Pawn hash hits  : {:.1}%", pawn_hits);
+
+        match full_nps {
+            Some(full_nps) => {
+                println!("Full PSQ        : {} nodes/second", full_nps);
+                println!("Incremental gain: {:+.1}%", 100.0 * (nps as f64 / full_nps as f64 - 1.0));
+            }
+            None => println!("Incremental gain: skipped with a network"),
+        }
+    }
+
+    // Each run starts from empty tables so that it searches the same trees
+    fn bench_search(search: &mut Search) -> (usize, time::Duration, OrderingStats) {
         let mut nodes: usize = 0;
-        let mut count: usize = 1;
+        let mut stats = OrderingStats::new();
+
+        search.eval.transposition_table.clear();
+        search.eval.pawn_table.clear();
+        search.clear_history();
+        // Only a go command can be stopped, a stop left over from a previous one is ignored
+        search.signals.stop.store(false, Ordering::Relaxed);
+
         let elapsed = time::Instant::now();
 
-        for fen in FENS {
-            println!("\nPosition: {}/{}, ({})", count, FENS.len(), fen);
-            count += 1;
+        for (count, fen) in FENS.iter().enumerate() {
+            println!("\nPosition: {}/{}, ({})", count + 1, FENS.len(), fen);
             search.position.set(fen.to_string());
             search.run(SearchLimits::default());
-            nodes += search.nodes_searched;
+            nodes += search.total_nodes();
+            stats.cutoffs += search.stats.cutoffs;
+            stats.first_move_cutoffs += search.stats.first_move_cutoffs;
         }
 
         let duration = time::Instant::now() - elapsed + time::Duration::from_millis(1); // Ensure positivity to avoid a 'divide by zero'
 
-        println!("\n===========================");
-        println!("Total time (ms) : {}", duration.as_millis());
-        println!("Nodes searched  : {}", nodes);
-        println!("Nodes/second    : {}", 1000 * nodes / duration.as_millis() as usize);
+        return (nodes, duration, stats);
+    }
+
+    // tune <data file> [epochs <n>] [output <file>]
+    // Fits the weights of the classical evaluation to a file of quiet positions labelled
+    // with game results. The tuned weights are written as Rust source to a .rs file, and as
+    // weights loadable with the EvalWeights option otherwise.
+    fn tune(search: &mut Search, args: &mut std::str::SplitWhitespace<'_>) {
+        let data_file: String = args.next().unwrap_or("").to_string();
+        let mut epochs: usize = DEFAULT_EPOCHS;
+        let mut output_file: String = "tuned_tables.rs".to_string();
+
+        while let Some(token) = args.next() {
+            match token {
+                "epochs" => {
+                    epochs = args
+                        .next()
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .unwrap_or(DEFAULT_EPOCHS)
+                }
+                "output" => output_file = args.next().unwrap_or(&output_file).to_string(),
+                _ => (),
+            }
+        }
+
+        // The weights belong to the classical evaluation
+        let mut position = search.position.clone();
+        position.set_network(None);
+
+        let mut tuner = Tuner::new(search.position.weights());
+        let skipped = match tuner.load(&data_file, &mut search.eval, &mut position) {
+            Ok(skipped) => skipped,
+            Err(error) => {
+                println!("{}", error);
+                return;
+            }
+        };
+
+        println!("Positions       : {} ({} lines skipped)", tuner.len(), skipped);
+        println!("Scaling         : {:.4}", tuner.fit_scaling());
+        println!("Initial loss    : {:.6}", tuner.loss());
+
+        tuner.run(epochs);
+
+        let written = match output_file.ends_with(".rs") {
+            true => std::fs::write(&output_file, tuner.tables_source())
+                .map_err(|error| format!("Cannot write {}: {}", output_file, error)),
+            false => tuner.weights().save(&output_file),
+        };
+
+        match written {
+            Ok(()) => println!("Weights written to {}", output_file),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    // Mate scores are converted to a number of moves, negative when the engine is getting mated
+    pub fn format_score(score: i16) -> String {
+        if score >= VALUE_MATE_IN_MAX_PLY {
+            return format!("mate {}", (VALUE_MATE - score + 1) / 2);
+        } else if score <= -VALUE_MATE_IN_MAX_PLY {
+            return format!("mate {}", -(VALUE_MATE + score) / 2);
+        }
+
+        return format!("cp {}", score);
     }
 
     fn help() {
-        println!("");
+        println!();
         println!("Oxide is a simple chess engine I built as a learning project.");
         println!("It is UCI compatible and can be used with any UCI compatible GUI.");
         println!("While not very strong yet but I am working on it and hoping to achieve a rating of 2000+.");
-        println!("");
+        println!();
+        println!("Commands besides UCI:");
+        println!("  bench               Searches the benchmark positions");
+        println!("  eval [json]         Shows the terms of the evaluation");
+        println!("  saveweights [file]  Writes the weights of the evaluation");
+        println!("  tune <data file> [epochs <n>] [output <file>]");
+        println!("                      Tunes the weights of the evaluation");
+        println!();
     }
 }
@@ -0,0 +1,45 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{bitboards::Bitboards, evaluate::Eval, movegen::Movegen, position::Position, search::Search, uci::UCI};
+
+    #[test]
+    fn go_mate_depth() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+
+        // A depth limits the search for a mate wherever it is given
+        for command in ["depth 5 mate 3", "mate 3 depth 5"] {
+            let limits = UCI::parse_go(&search, &mut command.split_whitespace());
+
+            assert_eq!(limits.mate, 3);
+            assert_eq!(limits.depth, 5);
+        }
+
+        let limits = UCI::parse_go(&search, &mut "mate 3".split_whitespace());
+        assert_eq!(limits.depth, u8::MAX);
+    }
+
+    #[test]
+    fn go_search_moves() {
+        let bitboards = Arc::new(Bitboards::new());
+        let movegen = Movegen::new(Arc::clone(&bitboards));
+        let position = Position::new(Arc::clone(&bitboards));
+        let search = Search::new(position, movegen, Eval::new(Arc::clone(&bitboards)));
+
+        let limits = UCI::parse_go(&search, &mut "searchmoves e2e4 e2e5 depth 3".split_whitespace());
+        assert_eq!(limits.search_moves.unwrap().len(), 1);
+        assert_eq!(limits.depth, 3);
+
+        // Invalid moves still restrict the search, to no move at all
+        let limits = UCI::parse_go(&search, &mut "searchmoves e2e5".split_whitespace());
+        assert_eq!(limits.search_moves, Some(Vec::new()));
+
+        let limits = UCI::parse_go(&search, &mut "depth 3".split_whitespace());
+        assert_eq!(limits.search_moves, None);
+    }
+}